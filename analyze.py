@@ -18,6 +18,8 @@ LEGEND_LOOKUP = {
     "7": "Lambda",
     "18": "Inline Assembly",
     "23": "Valid",
+    "100": "Decoding Failed",
+    "101": "Empty Input",
 }
 
 
@@ -80,6 +82,54 @@ def extract_per_client_counts(data):
     return client_counts, cat_order
 
 
+def extract_conformance_counts(data):
+    """Extract (in-grammar, step) counts from data (summed across all clients)."""
+    counts = defaultdict(float)
+
+    for cs in data.get("client_stats", {}).values():
+        s_absolute = (
+            cs.get("user_stats", {})
+            .get("conformance-absolute", {})
+            .get("value", {})
+            .get("String", "")
+        )
+        for pair in s_absolute.split(", "):
+            if ":" in pair:
+                key, count_str = pair.split(": ", 1)
+                grammar, step = key.split("-", 1)
+                counts[(grammar, step)] += float(count_str)
+
+    return counts
+
+
+def print_conformance_table(name, counts):
+    """Print the 2xN table of grammar conformance against the reached step."""
+    steps = sorted({step for _, step in counts}, key=natural_sort_key)
+    print(f"Grammar conformance by step for {name}:")
+    print("  " + " | ".join(["Grammar"] + [LEGEND_LOOKUP.get(s, s) for s in steps]))
+    for grammar in ["in", "out"]:
+        row = [f"{int(counts.get((grammar, step), 0))}" for step in steps]
+        print("  " + " | ".join([grammar] + row))
+
+
+def extract_grammar_coverage(data):
+    """Extract the grammar coverage stat of the client covering the most k-paths."""
+    best = None
+    for cs in data.get("client_stats", {}).values():
+        value = (
+            cs.get("user_stats", {})
+            .get("grammar-coverage", {})
+            .get("value", {})
+            .get("String", "")
+        )
+        if not value:
+            continue
+        paths = int(value.split(", ")[1].split(": ")[1].split("/")[0])
+        if best is None or paths > best[0]:
+            best = (paths, value)
+    return best[1] if best else None
+
+
 def interpolate_array(target_times, times, values):
     """Interpolate values at target_times using numpy's interp (vectorized, much faster)."""
     if len(times) == 0:
@@ -128,6 +178,8 @@ def process_log(log):
     )
     # Per-client data: client_id -> list of (time, category_counts dict)
     client_data = defaultdict(list)
+    conformance_counts = {}
+    grammar_coverage = None
 
     with open(log, "r") as f:
         print(f"Processing {log}")
@@ -149,6 +201,8 @@ def process_log(log):
                 break
 
             absolute_counts, new_cats = extract_absolute_counts(data)
+            conformance_counts = extract_conformance_counts(data) or conformance_counts
+            grammar_coverage = extract_grammar_coverage(data) or grammar_coverage
             if absolute_counts:
                 times_list.append(run_time)
                 executions_list.append(data.get("executions", 0))
@@ -316,6 +370,11 @@ def process_log(log):
     for label, ratio in zip(labels, final_cum_ratios):
         print(f"  {label}: {ratio:.4f}")
 
+    if conformance_counts:
+        print_conformance_table(name, conformance_counts)
+    if grammar_coverage:
+        print(f"Grammar coverage of the best client for {name}: {grammar_coverage}")
+
     # Store for LaTeX cumulative table
     cum = {"name": name, "labels": labels, "ratios": final_cum_ratios}
 
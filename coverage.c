@@ -58,4 +58,67 @@ __attribute__((visibility("default"))) uint32_t *get_guard_values()
   fprintf(stderr, "Returned guard values %p\n", guard_start);
 #endif
   return guard_start;
-}
\ No newline at end of file
+}
+// CmpLog: operands of comparisons, recorded with -fsanitize-coverage=trace-cmp. Each call site
+// is hashed to a slot keeping its last CMPLOG_VALUES operand pairs. The layout must match
+// `src/cmplog.rs`, the table is copied to the fuzzer's shared memory by
+// setup_guard_redirection.
+
+#define CMPLOG_SLOTS 8192
+#define CMPLOG_VALUES 8
+
+typedef struct
+{
+  uint32_t hits;
+  uint8_t size;
+  uint8_t is_const;
+  uint16_t padding;
+  uint64_t v0[CMPLOG_VALUES];
+  uint64_t v1[CMPLOG_VALUES];
+} cmplog_slot;
+
+static cmplog_slot cmplog_map[CMPLOG_SLOTS];
+
+static void cmplog_record(uintptr_t pc, uint8_t size, uint8_t is_const, uint64_t v0,
+                          uint64_t v1)
+{
+  cmplog_slot *slot = &cmplog_map[((pc >> 4) ^ (pc << 8)) & (CMPLOG_SLOTS - 1)];
+  uint32_t idx = slot->hits % CMPLOG_VALUES;
+  slot->hits += 1;
+  slot->size = size;
+  slot->is_const = is_const;
+  slot->v0[idx] = v0;
+  slot->v1[idx] = v1;
+}
+
+#define CMPLOG_CALLER ((uintptr_t)__builtin_return_address(0))
+
+void __sanitizer_cov_trace_cmp1(uint8_t a, uint8_t b) { cmplog_record(CMPLOG_CALLER, 1, 0, a, b); }
+void __sanitizer_cov_trace_cmp2(uint16_t a, uint16_t b) { cmplog_record(CMPLOG_CALLER, 2, 0, a, b); }
+void __sanitizer_cov_trace_cmp4(uint32_t a, uint32_t b) { cmplog_record(CMPLOG_CALLER, 4, 0, a, b); }
+void __sanitizer_cov_trace_cmp8(uint64_t a, uint64_t b) { cmplog_record(CMPLOG_CALLER, 8, 0, a, b); }
+
+// For comparisons against a constant, the constant is the first operand
+void __sanitizer_cov_trace_const_cmp1(uint8_t a, uint8_t b) { cmplog_record(CMPLOG_CALLER, 1, 1, a, b); }
+void __sanitizer_cov_trace_const_cmp2(uint16_t a, uint16_t b) { cmplog_record(CMPLOG_CALLER, 2, 1, a, b); }
+void __sanitizer_cov_trace_const_cmp4(uint32_t a, uint32_t b) { cmplog_record(CMPLOG_CALLER, 4, 1, a, b); }
+void __sanitizer_cov_trace_const_cmp8(uint64_t a, uint64_t b) { cmplog_record(CMPLOG_CALLER, 8, 1, a, b); }
+
+// cases[0] is the number of cases, cases[1] the operand size in bits, then the case values
+void __sanitizer_cov_trace_switch(uint64_t val, uint64_t *cases)
+{
+  uintptr_t pc = CMPLOG_CALLER;
+  uint8_t size = cases[1] / 8;
+  for (uint64_t i = 0; i < cases[0]; i++)
+    cmplog_record(pc + i, size, 1, cases[i + 2], val);
+}
+
+__attribute__((visibility("default"))) size_t get_cmplog_size()
+{
+  return sizeof(cmplog_map);
+}
+
+__attribute__((visibility("default"))) void *get_cmplog_map()
+{
+  return cmplog_map;
+}
@@ -8,4 +8,5 @@ export PYTHONPATH=$(echo .venv/lib/python*/site-packages)
 	--cores $F_CORES \
 	--broker-port "133$F_PORT" \
 	--target-binary "$TARGET_BINARY" \
-	--redirection-shared-library "$REDIRECTION_SHARED_LIBRARY"
+	--redirection-shared-library "$REDIRECTION_SHARED_LIBRARY" \
+	$F_ARGS
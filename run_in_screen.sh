@@ -1,6 +1,8 @@
 export F_OUT_DIR=fandango-interspersedhavoc-seedless
 export F_CORES="36-39"
 export F_PORT="9"
+# Additional arguments, e.g. "--post-mutations-max 10 --fandango-constraints constraints.fan"
+export F_ARGS=""
 
 # Copy LLVM directory to ramdisk if not already present
 RAMDISK_LLVM="/dev/shm/llvm"
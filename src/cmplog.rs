@@ -0,0 +1,102 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use libafl::{
+    observers::{cmp::CmpValues, Observer},
+    Error,
+};
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+// The layout of the comparison table in `coverage.c`
+
+const CMPLOG_SLOTS: usize = 8192;
+const CMPLOG_VALUES: usize = 8;
+/// `hits`, `size`, `is_const` and padding, then `v0` and `v1`
+const SLOT_SIZE: usize = 8 + 2 * 8 * CMPLOG_VALUES;
+
+/// Size of the comparison table that follows the edges in the shared memory when CmpLog is on
+pub const CMPLOG_MAP_SIZE: usize = CMPLOG_SLOTS * SLOT_SIZE;
+
+/// Reads the operands of the comparisons clang made for the last input, as recorded by
+/// `coverage.c` with `-fsanitize-coverage=trace-cmp`. Disabled without `--cmplog`.
+///
+/// The table is only decoded on request, see [`CmpLogTracingStage`](crate::stages::CmpLogTracingStage).
+#[allow(unused)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShMemCmpObserver {
+    #[serde(skip, default = "std::ptr::null")]
+    map_ptr: *const u8,
+    name: Cow<'static, str>,
+}
+
+impl ShMemCmpObserver {
+    /// `map` is the comparison table behind the edges, which is only there with `--cmplog`
+    #[allow(unused)]
+    pub fn new(map: &[u8]) -> Result<Self, Error> {
+        if map.len() < CMPLOG_MAP_SIZE {
+            return Err(Error::illegal_argument(
+                "The comparison table is only in the shared memory with --cmplog",
+            ));
+        }
+        Ok(Self {
+            map_ptr: map.as_ptr(),
+            name: Cow::Borrowed("cmplog"),
+        })
+    }
+
+    /// An observer without a table, for runs without `--cmplog`
+    #[allow(unused)]
+    pub fn disabled() -> Self {
+        Self {
+            map_ptr: std::ptr::null(),
+            name: Cow::Borrowed("cmplog"),
+        }
+    }
+
+    #[allow(unused)]
+    pub fn is_enabled(&self) -> bool {
+        !self.map_ptr.is_null()
+    }
+
+    /// The logged comparisons, each once, with constants as the first operand
+    #[allow(unused)]
+    pub fn cmp_values(&self) -> Vec<CmpValues> {
+        if !self.is_enabled() {
+            return vec![];
+        }
+        let map = unsafe { std::slice::from_raw_parts(self.map_ptr, CMPLOG_MAP_SIZE) };
+        let u64_at = |slot: &[u8], offset: usize| {
+            u64::from_ne_bytes(slot[offset..offset + 8].try_into().unwrap())
+        };
+
+        let mut seen = HashSet::new();
+        let mut values = vec![];
+        for slot in map.chunks_exact(SLOT_SIZE) {
+            let hits = u32::from_ne_bytes(slot[..4].try_into().unwrap()) as usize;
+            let (size, is_const) = (slot[4], slot[5] != 0);
+            for i in 0..hits.min(CMPLOG_VALUES) {
+                let v0 = u64_at(slot, 8 + 8 * i);
+                let v1 = u64_at(slot, 8 + 8 * (CMPLOG_VALUES + i));
+                // Equal operands leave nothing to replace
+                if v0 == v1 || !seen.insert((size, v0, v1, is_const)) {
+                    continue;
+                }
+                values.push(match size {
+                    1 => CmpValues::U8((v0 as u8, v1 as u8, is_const)),
+                    2 => CmpValues::U16((v0 as u16, v1 as u16, is_const)),
+                    4 => CmpValues::U32((v0 as u32, v1 as u32, is_const)),
+                    _ => CmpValues::U64((v0, v1, is_const)),
+                });
+            }
+        }
+        values
+    }
+}
+
+impl Named for ShMemCmpObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Observer<I, S> for ShMemCmpObserver {}
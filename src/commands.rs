@@ -0,0 +1,243 @@
+use std::{fs, path::PathBuf};
+
+use clap::Subcommand;
+use libafl::Error;
+use libafl_bolts::{current_nanos, rands::StdRand};
+
+use crate::{
+    grammar::{
+        self,
+        compare::{sample_rejections, Difference},
+        earley::Recognizer,
+        lint::{lint, Severity},
+        weights::{self, WeightMode},
+        Cfg,
+    },
+    tokenizer::strip_preprocessor,
+    Opt, NAUTILUS_MAX_DEPTH,
+};
+
+/// Number of rejected samples printed per direction
+const EXAMPLES: usize = 5;
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Convert a grammar between the .fan and Nautilus JSON formats, based on the file extensions
+    ConvertGrammar { input: PathBuf, output: PathBuf },
+
+    /// Compare the languages of two grammars by checking random derivations of each against the
+    /// other, ignoring whitespace unless --exact-whitespace is given
+    CompareGrammars {
+        left: PathBuf,
+        right: PathBuf,
+
+        #[arg(
+            long,
+            help = "Set the number of samples per grammar",
+            default_value = "1000"
+        )]
+        samples: usize,
+
+        #[arg(
+            long,
+            help = "Skip samples longer than this many bytes",
+            default_value = "4096"
+        )]
+        max_len: usize,
+
+        #[arg(long, help = "Override the start symbol of the left grammar")]
+        left_start: Option<String>,
+
+        #[arg(long, help = "Override the start symbol of the right grammar")]
+        right_start: Option<String>,
+
+        #[arg(long, help = "Compare including whitespace")]
+        exact_whitespace: bool,
+    },
+
+    /// Check grammars for undefined, unreachable, unproductive and too deep nonterminals,
+    /// defaulting to the .fan and .json grammar of --grammar-file-prefix
+    LintGrammar { grammars: Vec<PathBuf> },
+
+    /// Learn rule probabilities from the parses of a corpus, for --fan-weights and
+    /// --nautilus-weights
+    LearnWeights {
+        grammar: PathBuf,
+        output: PathBuf,
+
+        #[arg(
+            long,
+            help = "Read the corpus from this directory",
+            default_value = "valid_corpus"
+        )]
+        corpus: PathBuf,
+
+        #[arg(long, help = "Override the start symbol of the grammar")]
+        start: Option<String>,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Favor frequently or rarely used rules",
+            default_value = "frequent"
+        )]
+        mode: WeightMode,
+    },
+}
+
+fn load_with_start(path: &PathBuf, start: Option<&String>) -> Result<Cfg, Error> {
+    let mut cfg = grammar::load(path)?;
+    if let Some(start) = start {
+        cfg.start = cfg.nonterminal_id(start).ok_or_else(|| {
+            Error::illegal_argument(format!("{} has no nonterminal {start}", path.display()))
+        })?;
+    }
+    Ok(cfg)
+}
+
+fn print_difference(from: &PathBuf, by: &PathBuf, difference: &Difference) {
+    let checked = difference.sampled - difference.skipped;
+    println!(
+        "{} of {checked} samples of {} are rejected by {} ({} too long to check)",
+        difference.rejected.len(),
+        from.display(),
+        by.display(),
+        difference.skipped,
+    );
+    for example in difference.rejected.iter().take(EXAMPLES) {
+        println!("  {:?}", String::from_utf8_lossy(example));
+    }
+}
+
+/// The grammars given by `--grammar-file-prefix` that exist
+fn default_grammars(opt: &Opt) -> Vec<PathBuf> {
+    ["fan", "json"]
+        .iter()
+        .map(|ext| PathBuf::from(format!("{}.{ext}", opt.grammar_file_prefix)))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Lints the grammars used for fuzzing, printing errors and the number of warnings.
+///
+/// Fails if any grammar has errors, since fuzzing with it would panic or not generate anything.
+pub fn lint_before_fuzzing(opt: &Opt) -> Result<(), Error> {
+    let mut errors = 0;
+    for path in default_grammars(opt) {
+        let findings = lint(&grammar::load(&path)?, NAUTILUS_MAX_DEPTH);
+        let (path_errors, warnings): (Vec<_>, Vec<_>) = findings
+            .iter()
+            .partition(|finding| finding.severity == Severity::Error);
+        for finding in &path_errors {
+            eprintln!("{}: {finding}", path.display());
+        }
+        if !warnings.is_empty() {
+            println!(
+                "{}: {} grammar warnings, see lint-grammar",
+                path.display(),
+                warnings.len()
+            );
+        }
+        errors += path_errors.len();
+    }
+    if errors > 0 {
+        return Err(Error::illegal_argument(format!(
+            "{errors} grammar errors, not fuzzing"
+        )));
+    }
+    Ok(())
+}
+
+pub fn run(command: &Command, opt: &Opt) -> Result<(), Error> {
+    match command {
+        Command::ConvertGrammar { input, output } => {
+            let cfg = grammar::load(input)?;
+            grammar::store(&cfg, output)?;
+            println!(
+                "Converted {} rules of {} to {}",
+                cfg.rules.len(),
+                input.display(),
+                output.display()
+            );
+        }
+        Command::CompareGrammars {
+            left,
+            right,
+            samples,
+            max_len,
+            left_start,
+            right_start,
+            exact_whitespace,
+        } => {
+            let left_cfg = load_with_start(left, left_start.as_ref())?;
+            let right_cfg = load_with_start(right, right_start.as_ref())?;
+            let left_recognizer = Recognizer::new(&left_cfg, !exact_whitespace);
+            let right_recognizer = Recognizer::new(&right_cfg, !exact_whitespace);
+            let left_generator = opt.fan.generator_for(left_cfg)?;
+            let right_generator = opt.fan.generator_for(right_cfg)?;
+
+            let mut rand = StdRand::with_seed(current_nanos());
+            let left_only = sample_rejections(
+                &left_generator,
+                &right_recognizer,
+                *samples,
+                *max_len,
+                &mut rand,
+            );
+            let right_only = sample_rejections(
+                &right_generator,
+                &left_recognizer,
+                *samples,
+                *max_len,
+                &mut rand,
+            );
+            print_difference(left, right, &left_only);
+            print_difference(right, left, &right_only);
+        }
+        Command::LintGrammar { grammars } => {
+            let grammars = if grammars.is_empty() {
+                default_grammars(opt)
+            } else {
+                grammars.clone()
+            };
+            let mut errors = 0;
+            for path in &grammars {
+                for finding in lint(&grammar::load(path)?, NAUTILUS_MAX_DEPTH) {
+                    println!("{}: {finding}", path.display());
+                    if finding.severity == Severity::Error {
+                        errors += 1;
+                    }
+                }
+            }
+            if errors > 0 {
+                return Err(Error::illegal_argument(format!("{errors} grammar errors")));
+            }
+        }
+        Command::LearnWeights {
+            grammar,
+            output,
+            corpus,
+            start,
+            mode,
+        } => {
+            let cfg = load_with_start(grammar, start.as_ref())?;
+            let mut inputs = vec![];
+            for entry in fs::read_dir(corpus)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    inputs.push(strip_preprocessor(&fs::read(path)?));
+                }
+            }
+            let (counts, parsed) = weights::count_rules(&cfg, &inputs);
+            let probabilities = weights::probabilities(&cfg, &counts, *mode);
+            weights::store(&cfg, &counts, &probabilities, output)?;
+            println!(
+                "Parsed {parsed} of {} files of {}, wrote {}",
+                inputs.len(),
+                corpus.display(),
+                output.display()
+            );
+        }
+    }
+    Ok(())
+}
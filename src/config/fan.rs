@@ -0,0 +1,141 @@
+use std::{marker::PhantomData, path::PathBuf};
+
+use clap::Args;
+use libafl::{inputs::BytesInput, observers::ObserversTuple, schedulers::QueueScheduler, Error};
+use libafl_bolts::{current_nanos, rands::StdRand, tuples::Handle};
+
+use crate::{
+    config::{seeds::SeedsConfig, FuzzerConfig},
+    executor::{get_executor, GenericExecutor},
+    feedback::GrammarCoverageFeedback,
+    grammar::{fan, generator::CfgGenerator, weights, Cfg},
+    observer::CorrectnessObserver,
+    Opt, NUM_GENERATED,
+};
+
+#[derive(Debug, Args)]
+pub struct FanOpt {
+    #[arg(
+        long = "fan-max-depth",
+        help = "Set the maximum derivation depth of the Rust .fan generator, default is 64",
+        name = "FAN_MAX_DEPTH",
+        default_value = "64"
+    )]
+    max_depth: usize,
+
+    #[arg(
+        long = "fan-max-size",
+        help = "Set the number of derivation nodes after which the Rust .fan generator only picks the shortest expansions, default is 2000",
+        name = "FAN_MAX_SIZE",
+        default_value = "2000"
+    )]
+    max_size: usize,
+
+    #[arg(
+        long = "fan-weights",
+        help = "Pick the rules of the Rust .fan generator with the probabilities from this file, see learn-weights",
+        name = "FAN_WEIGHTS"
+    )]
+    weights: Option<PathBuf>,
+}
+
+impl FanOpt {
+    /// A generator for the `.fan` grammar given by `opt`
+    pub fn generator(&self, opt: &Opt) -> Result<CfgGenerator, Error> {
+        let generator =
+            self.generator_for(fan::from_file(format!("{}.fan", opt.grammar_file_prefix))?)?;
+        match &self.weights {
+            Some(path) => {
+                let weights = weights::load(generator.cfg(), path)?;
+                Ok(generator.with_weights(weights))
+            }
+            None => Ok(generator),
+        }
+    }
+
+    /// A generator for any grammar, using the configured depth and size limits
+    pub fn generator_for(&self, cfg: Cfg) -> Result<CfgGenerator, Error> {
+        CfgGenerator::new(cfg, self.max_depth, self.max_size)
+    }
+}
+
+/// Grammar-based generation from `.fan` files without Fandango, i.e. without Python
+#[allow(unused)]
+pub struct FanConfig<Seeds: SeedsConfig>(PhantomData<Seeds>);
+
+impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for FanConfig<Seeds> {
+    type Scheduler<'a> = QueueScheduler;
+
+    type EdgesObserver<'a> = super::SchedulerObserver<'a>;
+
+    fn edges_observer<'a>(observer: super::SchedulerObserver<'a>) -> Self::EdgesObserver<'a> {
+        observer
+    }
+
+    fn scheduler<'a>(_observer: &Self::EdgesObserver<'a>) -> Self::Scheduler<'a> {
+        QueueScheduler::new()
+    }
+
+    type Input = BytesInput;
+
+    fn initial_inputs(_init: &mut Self::Init, opt: &Opt) -> Vec<Self::Input> {
+        let generator = opt.fan.generator(opt).unwrap();
+        let mut rand = StdRand::with_seed(current_nanos());
+        let mut inputs = (0..NUM_GENERATED)
+            .map(|_| {
+                let mut bytes = vec![];
+                generator
+                    .cfg()
+                    .unparse(&generator.derive(&mut rand), &mut bytes);
+                BytesInput::new(bytes)
+            })
+            .collect::<Vec<_>>();
+        inputs.extend(Seeds::get_seeds().into_iter().map(BytesInput::new));
+        inputs
+    }
+
+    type Init = ();
+
+    fn init() -> Self::Init {}
+
+    type Executor<'a, OT, S> = GenericExecutor<BytesInput, OT, S>;
+
+    type GrammarCoverage = GrammarCoverageFeedback;
+
+    fn grammar_coverage(feedback: GrammarCoverageFeedback) -> Self::GrammarCoverage {
+        feedback
+    }
+
+    fn get_executor<'a, OT: ObserversTuple<BytesInput, S>, S>(
+        _init: &'a mut Self::Init,
+        stdout_observer: libafl::observers::StdOutObserver,
+        stderr_observer: libafl::observers::StdErrObserver,
+        observers: OT,
+        _correctness: Handle<CorrectnessObserver>,
+        shmem_description: libafl_bolts::shmem::ShMemDescription,
+        opt: &Opt,
+    ) -> Result<Self::Executor<'a, OT, S>, Error> {
+        get_executor(
+            stdout_observer,
+            stderr_observer,
+            observers,
+            shmem_description,
+            opt.redirection_shared_library.to_str().unwrap(),
+            opt.target_binary.to_str().unwrap(),
+        )
+    }
+}
+
+/// Continuously generates from the `.fan` grammar, applying `$inner` between
+/// `--post-mutations-min` and `--post-mutations-max` times to each generated input
+#[allow(unused_macros)]
+macro_rules! setup_fan_stages {
+    ($opt:expr, $inner:expr) => {{
+        tuple_list!(crate::stages::GeneratorPostMutationalStage::new(
+            $opt.fan.generator($opt).unwrap(),
+            $inner,
+            $opt.post_mutations_min,
+            $opt.post_mutations_max
+        ))
+    }};
+}
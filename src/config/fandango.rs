@@ -1,25 +1,142 @@
-use std::marker::PhantomData;
+use std::{fs, marker::PhantomData, path::PathBuf};
 
+use clap::Args;
 use libafl::{inputs::BytesInput, observers::ObserversTuple, schedulers::QueueScheduler, Error};
+use libafl_bolts::tuples::Handle;
 
 use crate::{
     config::{seeds::SeedsConfig, FuzzerConfig},
     executor::{get_executor, GenericExecutor},
+    feedback::GrammarCoverageFeedback,
+    observer::CorrectnessObserver,
+    Opt,
 };
 
+#[derive(Debug, Args)]
+pub struct FandangoOpt {
+    #[arg(
+        long = "fandango-constraints",
+        help = "Add a .fan file with constraints to the grammar passed to Fandango, may be repeated",
+        name = "FANDANGO_CONSTRAINTS"
+    )]
+    constraints: Vec<PathBuf>,
+
+    #[arg(
+        long = "fandango-population-size",
+        help = "Set Fandango's population size",
+        name = "FANDANGO_POPULATION_SIZE"
+    )]
+    population_size: Option<usize>,
+
+    #[arg(
+        long = "fandango-max-generations",
+        help = "Set the maximum number of generations Fandango evolves per population",
+        name = "FANDANGO_MAX_GENERATIONS"
+    )]
+    max_generations: Option<usize>,
+
+    #[arg(
+        long = "fandango-seed",
+        help = "Set Fandango's random seed",
+        name = "FANDANGO_SEED"
+    )]
+    seed: Option<u64>,
+
+    #[arg(
+        long = "fandango-workers",
+        help = "Set the number of background threads generating inputs with Fandango, default is 1",
+        name = "FANDANGO_WORKERS",
+        default_value = "1"
+    )]
+    pub workers: usize,
+
+    #[arg(
+        long = "fandango-batch-size",
+        help = "Set the number of inputs a Fandango worker generates per batch, default is 16",
+        name = "FANDANGO_BATCH_SIZE",
+        default_value = "16"
+    )]
+    pub batch_size: usize,
+
+    #[arg(
+        long = "fandango-queue-capacity",
+        help = "Set the number of batches kept ready for the fuzzer, default is 4",
+        name = "FANDANGO_QUEUE_CAPACITY",
+        default_value = "4"
+    )]
+    pub queue_capacity: usize,
+}
+
+impl FandangoOpt {
+    /// The `.fan` file to load, the one written by [`FandangoOpt::write_combined_grammar`] if
+    /// there are constraints and the grammar file itself otherwise
+    pub fn grammar_file(&self, opt: &Opt) -> String {
+        if self.constraints.is_empty() {
+            format!("{}.fan", opt.grammar_file_prefix)
+        } else {
+            Self::combined_file(opt).to_string_lossy().to_string()
+        }
+    }
+
+    fn combined_file(opt: &Opt) -> PathBuf {
+        opt.output.join("fandango-combined.fan")
+    }
+
+    /// Writes the grammar with all constraint files appended to it, if there are any.
+    ///
+    /// Called once before the clients are launched, which all read the same file.
+    pub fn write_combined_grammar(&self, opt: &Opt) -> Result<(), Error> {
+        if self.constraints.is_empty() {
+            return Ok(());
+        }
+
+        let mut combined = fs::read_to_string(format!("{}.fan", opt.grammar_file_prefix))?;
+        for constraint_file in &self.constraints {
+            combined.push('\n');
+            combined.push_str(&fs::read_to_string(constraint_file)?);
+        }
+        fs::write(Self::combined_file(opt), combined)?;
+        Ok(())
+    }
+
+    /// Additional parameters passed on to Fandango
+    pub fn parameters(&self) -> Vec<(&'static str, String)> {
+        [
+            (
+                "population_size",
+                self.population_size.map(|v| v.to_string()),
+            ),
+            (
+                "max_generations",
+                self.max_generations.map(|v| v.to_string()),
+            ),
+            ("random_seed", self.seed.map(|v| v.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| v.map(|v| (k, v)))
+        .collect()
+    }
+}
+
 #[allow(unused)]
 pub struct FandangoConfig<Seeds: SeedsConfig>(PhantomData<Seeds>);
 
 impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for FandangoConfig<Seeds> {
     type Scheduler<'a> = QueueScheduler;
 
-    fn scheduler<'a>(_observer: &super::SchedulerObserver<'a>) -> Self::Scheduler<'a> {
+    type EdgesObserver<'a> = super::SchedulerObserver<'a>;
+
+    fn edges_observer<'a>(observer: super::SchedulerObserver<'a>) -> Self::EdgesObserver<'a> {
+        observer
+    }
+
+    fn scheduler<'a>(_observer: &Self::EdgesObserver<'a>) -> Self::Scheduler<'a> {
         QueueScheduler::new()
     }
 
     type Input = BytesInput;
 
-    fn initial_inputs(_init: &mut Self::Init, _opt: &crate::Opt) -> Vec<Self::Input> {
+    fn initial_inputs(_init: &mut Self::Init, _opt: &Opt) -> Vec<Self::Input> {
         let mut inputs = vec![];
         inputs.extend_from_slice(
             &Seeds::get_seeds()
@@ -39,38 +156,103 @@ impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for FandangoConfig<Seeds> {
 
     type Executor<'a, OT, S> = GenericExecutor<BytesInput, OT, S>;
 
+    type GrammarCoverage = GrammarCoverageFeedback;
+
+    fn grammar_coverage(feedback: GrammarCoverageFeedback) -> Self::GrammarCoverage {
+        feedback
+    }
+
     fn get_executor<'a, OT: ObserversTuple<BytesInput, S>, S>(
         _init: &'a mut Self::Init,
         stdout_observer: libafl::observers::StdOutObserver,
         stderr_observer: libafl::observers::StdErrObserver,
         observers: OT,
+        _correctness: Handle<CorrectnessObserver>,
         shmem_description: libafl_bolts::shmem::ShMemDescription,
-        redirection_shared_library: &str,
-        target_binary: &str,
+        opt: &Opt,
     ) -> Result<Self::Executor<'a, OT, S>, Error> {
         get_executor(
             stdout_observer,
             stderr_observer,
             observers,
             shmem_description,
-            redirection_shared_library,
-            target_binary,
+            opt.redirection_shared_library.to_str().unwrap(),
+            opt.target_binary.to_str().unwrap(),
         )
     }
 }
 
 #[allow(unused_macros)]
 macro_rules! setup_fandango_stages {
-    ($opt:expr, $inner:expr, $min:expr, $max:expr) => {{
-        let module = libafl_fandango_pyo3::fandango::FandangoPythonModule::new(
-            &format!("{}.fan", $opt.grammar_file_prefix),
-            &[],
-        )
-        .unwrap();
+    ($opt:expr, $inner:expr) => {{
+        let grammar_file = $opt.fandango.grammar_file($opt);
+        let parameters = $opt.fandango.parameters();
+        let parameters = parameters
+            .iter()
+            .map(|(k, v)| (*k, v.as_str()))
+            .collect::<Vec<_>>();
+        let module =
+            libafl_fandango_pyo3::fandango::FandangoPythonModule::new(&grammar_file, &parameters)
+                .unwrap();
         tuple_list!(
             libafl_fandango_pyo3::libafl::FandangoPostMutationalStage::new(
-                module, $inner, $min, $max
+                module,
+                $inner,
+                $opt.post_mutations_min,
+                $opt.post_mutations_max
             )
         )
     }};
 }
+
+/// Like `setup_fandango_stages!`, but generating through a `FandangoPool` off the fuzzing thread
+#[allow(unused_macros)]
+macro_rules! setup_fandango_pool_stages {
+    ($opt:expr, $inner:expr) => {{
+        let pool = crate::config::fandango_pool::FandangoPool::new(
+            &$opt.fandango.grammar_file($opt),
+            &$opt.fandango.parameters(),
+            $opt.fandango.workers,
+            $opt.fandango.batch_size,
+            $opt.fandango.queue_capacity,
+        );
+        tuple_list!(crate::stages::GeneratorPostMutationalStage::new(
+            pool,
+            $inner,
+            $opt.post_mutations_min,
+            $opt.post_mutations_max
+        ))
+    }};
+}
+
+/// Like `setup_fandango_pool_stages!`, but blames the rules of the generated inputs, recovered by
+/// parsing them with the `.fan` grammar, see [`RuleBlameHook`](crate::stages::RuleBlameHook).
+/// The blame is written to `rule-blame-<client>.json` in the output directory.
+#[allow(unused_macros)]
+macro_rules! setup_fandango_blame_stages {
+    ($opt:expr, $inner:expr, $correctness:expr, $stderr:expr, $client:expr) => {{
+        let pool = crate::config::fandango_pool::FandangoPool::new(
+            &$opt.fandango.grammar_file($opt),
+            &$opt.fandango.parameters(),
+            $opt.fandango.workers,
+            $opt.fandango.batch_size,
+            $opt.fandango.queue_capacity,
+        );
+        let cfg =
+            crate::grammar::fan::from_file(format!("{}.fan", $opt.grammar_file_prefix)).unwrap();
+        let blame_file = $opt
+            .output
+            .join(format!("rule-blame-{}.json", $client.id()));
+        tuple_list!(crate::stages::GeneratorPostMutationalStage::new(
+            crate::stages::blame::ParsingGenerator::new(pool, cfg),
+            $inner,
+            $opt.post_mutations_min,
+            $opt.post_mutations_max
+        )
+        .with_hook(crate::stages::RuleBlameHook::new(
+            $correctness,
+            $stderr,
+            blame_file
+        )))
+    }};
+}
@@ -0,0 +1,95 @@
+use std::{
+    sync::mpsc::{sync_channel, Receiver},
+    thread,
+};
+
+use libafl::{generators::Generator, inputs::BytesInput, Error};
+use libafl_fandango_pyo3::fandango::FandangoPythonModule;
+
+/// Runs Fandango on background threads and hands its outputs to the fuzzer through a bounded
+/// queue of batches, so the fuzzing thread only waits if the workers fall behind.
+///
+/// All workers share one Python interpreter, so more than one worker only helps as far as
+/// Fandango releases the GIL.
+#[allow(unused)]
+pub struct FandangoPool {
+    receiver: Receiver<Vec<Vec<u8>>>,
+    batch: Vec<Vec<u8>>,
+}
+
+impl FandangoPool {
+    #[allow(unused)]
+    pub fn new(
+        grammar_file: &str,
+        parameters: &[(&'static str, String)],
+        workers: usize,
+        batch_size: usize,
+        queue_capacity: usize,
+    ) -> Self {
+        assert!(workers > 0, "Need at least one Fandango worker");
+        assert!(batch_size > 0, "Fandango batches must not be empty");
+
+        let (sender, receiver) = sync_channel(queue_capacity);
+        for worker in 0..workers {
+            let sender = sender.clone();
+            let grammar_file = grammar_file.to_string();
+            let parameters = worker_parameters(parameters, worker);
+            thread::spawn(move || {
+                let parameters = parameters
+                    .iter()
+                    .map(|(k, v)| (*k, v.as_str()))
+                    .collect::<Vec<_>>();
+                let mut module = FandangoPythonModule::new(&grammar_file, &parameters).unwrap();
+                loop {
+                    let batch = (0..batch_size)
+                        .map(|_| module.next_input())
+                        .collect::<Result<Vec<_>, _>>();
+                    match batch {
+                        // The receiving end is gone once the fuzzer shuts down
+                        Ok(batch) => {
+                            if sender.send(batch).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Fandango worker {worker} failed: {e:?}");
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
+        Self {
+            receiver,
+            batch: vec![],
+        }
+    }
+}
+
+/// The parameters of worker `worker`: a `random_seed` is offset by the worker's index, so the
+/// workers do not all produce the same inputs.
+fn worker_parameters(
+    parameters: &[(&'static str, String)],
+    worker: usize,
+) -> Vec<(&'static str, String)> {
+    parameters
+        .iter()
+        .map(|(k, v)| match (*k, v.parse::<u64>()) {
+            ("random_seed", Ok(seed)) => (*k, seed.wrapping_add(worker as u64).to_string()),
+            _ => (*k, v.clone()),
+        })
+        .collect()
+}
+
+impl<S> Generator<BytesInput, S> for FandangoPool {
+    fn generate(&mut self, _state: &mut S) -> Result<BytesInput, Error> {
+        if self.batch.is_empty() {
+            self.batch = self
+                .receiver
+                .recv()
+                .map_err(|_| Error::illegal_state("All Fandango workers stopped"))?;
+        }
+        Ok(BytesInput::new(self.batch.pop().unwrap()))
+    }
+}
@@ -0,0 +1,157 @@
+use std::marker::PhantomData;
+
+use clap::Args;
+use libafl::{
+    generators::{gramatron::Automaton, Generator, GramatronGenerator},
+    inputs::{BytesInput, GramatronInput},
+    observers::ObserversTuple,
+    schedulers::QueueScheduler,
+    state::{HasRand, NopState},
+    Error,
+};
+use libafl_bolts::{current_nanos, rands::Rand, tuples::Handle};
+
+use crate::{
+    config::{nautilus::PROGRAM_START, seeds::SeedsConfig, FuzzerConfig},
+    executor::{get_executor, GenericExecutor, Unparser, UnparsingExecutor},
+    feedback::GrammarCoverageFeedback,
+    grammar::gramatron::automaton,
+    observer::CorrectnessObserver,
+    Opt, NUM_GENERATED,
+};
+
+/// Upper bound on the number of automaton states, to fail early on too large stack bounds
+const MAX_STATES: usize = 1_000_000;
+
+#[derive(Debug, Args)]
+pub struct GramatronOpt {
+    #[arg(
+        long = "gramatron-max-stack",
+        help = "Set the stack bound when compiling the Nautilus grammar into a Gramatron automaton, default is 8",
+        name = "GRAMATRON_MAX_STACK",
+        default_value = "8"
+    )]
+    max_stack: usize,
+}
+
+impl GramatronOpt {
+    /// The automaton of the Nautilus grammar given by `opt`, starting at `--nautilus-start` or
+    /// else [`PROGRAM_START`]
+    pub fn automaton(&self, opt: &Opt) -> Result<Automaton, Error> {
+        let (cfg, _) = opt.nautilus.or_start(PROGRAM_START).grammar(opt)?;
+        let automaton = automaton(&cfg, self.max_stack, MAX_STATES)?;
+        println!(
+            "Gramatron automaton with {} states and {} transitions",
+            automaton.pda.len(),
+            automaton.pda.iter().map(Vec::len).sum::<usize>()
+        );
+        Ok(automaton)
+    }
+}
+
+/// Gramatron on walks of an automaton compiled from the Nautilus grammar.
+///
+/// Seeds are ignored, they would have to be matched against the automaton. The initial inputs
+/// are all generated.
+#[allow(unused)]
+pub struct GramatronConfig<Seeds: SeedsConfig>(PhantomData<Seeds>);
+
+impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for GramatronConfig<Seeds> {
+    type Scheduler<'a> = QueueScheduler;
+
+    type EdgesObserver<'a> = super::SchedulerObserver<'a>;
+
+    fn edges_observer<'a>(observer: super::SchedulerObserver<'a>) -> Self::EdgesObserver<'a> {
+        observer
+    }
+
+    fn scheduler<'a>(_observer: &Self::EdgesObserver<'a>) -> Self::Scheduler<'a> {
+        QueueScheduler::new()
+    }
+
+    type Input = GramatronInput;
+
+    fn initial_inputs(_init: &mut Self::Init, opt: &Opt) -> Vec<Self::Input> {
+        let automaton = opt.gramatron.automaton(opt).unwrap();
+        let mut generator = GramatronGenerator::new(&automaton);
+        let mut state = NopState::<GramatronInput>::new();
+        state.rand_mut().set_seed(current_nanos());
+        if !Seeds::get_seeds().is_empty() {
+            println!("GramatronConfig ignores the seeds");
+        }
+        (0..NUM_GENERATED)
+            .map(|_| generator.generate(&mut state).unwrap())
+            .collect()
+    }
+
+    type Init = ();
+
+    fn init() -> Self::Init {}
+
+    type Executor<'a, OT, S> =
+        UnparsingExecutor<GenericExecutor<BytesInput, OT, S>, GramatronUnparser>;
+
+    type GrammarCoverage = GrammarCoverageFeedback;
+
+    fn grammar_coverage(feedback: GrammarCoverageFeedback) -> Self::GrammarCoverage {
+        feedback
+    }
+
+    fn get_executor<'a, OT: ObserversTuple<BytesInput, S>, S>(
+        _init: &'a mut Self::Init,
+        stdout_observer: libafl::observers::StdOutObserver,
+        stderr_observer: libafl::observers::StdErrObserver,
+        observers: OT,
+        correctness: Handle<CorrectnessObserver>,
+        shmem_description: libafl_bolts::shmem::ShMemDescription,
+        opt: &Opt,
+    ) -> Result<Self::Executor<'a, OT, S>, Error> {
+        let inner = get_executor(
+            stdout_observer,
+            stderr_observer,
+            observers,
+            shmem_description,
+            opt.redirection_shared_library.to_str().unwrap(),
+            opt.target_binary.to_str().unwrap(),
+        )?;
+        Ok(UnparsingExecutor::new(
+            inner,
+            GramatronUnparser,
+            correctness,
+            opt.terminator,
+        ))
+    }
+}
+
+/// Unparses [`GramatronInput`]s, which keep the terminals of their walk
+#[derive(Debug, Clone, Copy)]
+pub struct GramatronUnparser;
+
+impl Unparser<GramatronInput> for GramatronUnparser {
+    fn unparse(&mut self, input: &GramatronInput, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        input.unparse(bytes);
+        Ok(())
+    }
+}
+
+/// Gramatron's random, splice and recursion mutations, weighted as in LibAFL's Gramatron example.
+///
+/// `$generator` is a `GramatronGenerator` that outlives the stages, for the random mutations.
+#[allow(unused_macros)]
+macro_rules! setup_gramatron_stages {
+    ($generator:expr) => {{
+        tuple_list!(libafl::stages::StdMutationalStage::new(
+            libafl::mutators::HavocScheduledMutator::with_max_stack_pow(
+                tuple_list!(
+                    libafl::mutators::GramatronRandomMutator::new($generator),
+                    libafl::mutators::GramatronRandomMutator::new($generator),
+                    libafl::mutators::GramatronRandomMutator::new($generator),
+                    libafl::mutators::GramatronSpliceMutator::new(),
+                    libafl::mutators::GramatronSpliceMutator::new(),
+                    libafl::mutators::GramatronRecursionMutator::new(),
+                ),
+                2
+            )
+        ))
+    }};
+}
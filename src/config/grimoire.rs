@@ -0,0 +1,111 @@
+use std::marker::PhantomData;
+
+use libafl::{
+    feedbacks::ConstFeedback,
+    inputs::BytesInput,
+    observers::{CanTrack, ObserversTuple},
+    schedulers::QueueScheduler,
+    Error,
+};
+use libafl_bolts::tuples::Handle;
+
+use crate::{
+    config::{seeds::SeedsConfig, FuzzerConfig},
+    executor::{get_executor, GenericExecutor},
+    feedback::GrammarCoverageFeedback,
+    observer::CorrectnessObserver,
+    Opt,
+};
+
+/// Grimoire, which infers the structure of its inputs instead of being given a grammar
+#[allow(unused)]
+pub struct GrimoireConfig<Seeds: SeedsConfig>(PhantomData<Seeds>);
+
+impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for GrimoireConfig<Seeds> {
+    type Scheduler<'a> = QueueScheduler;
+
+    // The generalization stage needs the novelties of each new corpus entry
+    type EdgesObserver<'a> = libafl::observers::ExplicitTracking<
+        libafl::observers::HitcountsMapObserver<libafl::observers::StdMapObserver<'a, u8, false>>,
+        true,
+        true,
+    >;
+
+    fn edges_observer<'a>(observer: super::SchedulerObserver<'a>) -> Self::EdgesObserver<'a> {
+        observer.track_novelties()
+    }
+
+    fn scheduler<'a>(_observer: &Self::EdgesObserver<'a>) -> Self::Scheduler<'a> {
+        QueueScheduler::new()
+    }
+
+    type Input = BytesInput;
+
+    fn initial_inputs(_init: &mut Self::Init, _opt: &Opt) -> Vec<Self::Input> {
+        let mut inputs = Seeds::get_seeds()
+            .into_iter()
+            .map(BytesInput::new)
+            .collect::<Vec<_>>();
+        if inputs.is_empty() {
+            inputs.push(BytesInput::new(vec![]));
+        }
+        inputs
+    }
+
+    type Init = ();
+
+    fn init() -> Self::Init {}
+
+    type Executor<'a, OT, S> = GenericExecutor<BytesInput, OT, S>;
+
+    // Grimoire is given no grammar, so there is no grammar coverage to guide it
+    type GrammarCoverage = ConstFeedback;
+
+    fn grammar_coverage(_feedback: GrammarCoverageFeedback) -> Self::GrammarCoverage {
+        ConstFeedback::False
+    }
+
+    fn get_executor<'a, OT: ObserversTuple<BytesInput, S>, S>(
+        _init: &'a mut Self::Init,
+        stdout_observer: libafl::observers::StdOutObserver,
+        stderr_observer: libafl::observers::StdErrObserver,
+        observers: OT,
+        _correctness: Handle<CorrectnessObserver>,
+        shmem_description: libafl_bolts::shmem::ShMemDescription,
+        opt: &Opt,
+    ) -> Result<Self::Executor<'a, OT, S>, Error> {
+        get_executor(
+            stdout_observer,
+            stderr_observer,
+            observers,
+            shmem_description,
+            opt.redirection_shared_library.to_str().unwrap(),
+            opt.target_binary.to_str().unwrap(),
+        )
+    }
+}
+
+/// Generalizes new corpus entries using the edges map at `$edges`, then applies Grimoire's
+/// mutations to the generalized inputs, as in LibAFL's Grimoire example
+#[allow(unused_macros)]
+macro_rules! setup_grimoire_stages {
+    ($executor:expr, $edges:expr) => {{
+        use libafl::executors::HasObservers as _;
+        tuple_list!(
+            libafl::stages::GeneralizationStage::new(&$executor.observers()[&$edges]),
+            libafl::stages::StdMutationalStage::transforming(
+                libafl::mutators::HavocScheduledMutator::with_max_stack_pow(
+                    tuple_list!(
+                        libafl::mutators::GrimoireExtensionMutator::new(),
+                        libafl::mutators::GrimoireRecursiveReplacementMutator::new(),
+                        libafl::mutators::GrimoireStringReplacementMutator::new(),
+                        // Deleting more often keeps the inputs from growing too large
+                        libafl::mutators::GrimoireRandomDeleteMutator::new(),
+                        libafl::mutators::GrimoireRandomDeleteMutator::new(),
+                    ),
+                    3
+                )
+            )
+        )
+    }};
+}
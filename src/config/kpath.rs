@@ -0,0 +1,148 @@
+use std::{borrow::Cow, marker::PhantomData};
+
+use clap::Args;
+use libafl::{
+    events::EventFirer, inputs::BytesInput, observers::ObserversTuple, schedulers::QueueScheduler,
+    state::HasExecutions, Error, HasMetadata,
+};
+use libafl_bolts::{current_nanos, rands::StdRand, tuples::Handle};
+
+use crate::{
+    config::{seeds::SeedsConfig, FuzzerConfig},
+    executor::{get_executor, GenericExecutor},
+    feedback::{report_string, GrammarCoverageFeedback},
+    grammar::kpath::{KPathGenerator, KPathProgress},
+    observer::CorrectnessObserver,
+    stages::post_mutational::GeneratedInputHook,
+    Opt, NUM_GENERATED,
+};
+
+#[derive(Debug, Args)]
+pub struct KPathOpt {
+    #[arg(
+        long = "kpath-k",
+        help = "Set the length of the rule paths the k-path generator covers, default is 2",
+        name = "KPATH_K",
+        default_value = "2"
+    )]
+    k: usize,
+}
+
+impl KPathOpt {
+    /// A k-path generator for the `.fan` grammar given by `opt`, with the `--fan-*` limits
+    pub fn generator(&self, opt: &Opt) -> Result<KPathGenerator, Error> {
+        KPathGenerator::new(opt.fan.generator(opt)?, self.k)
+    }
+}
+
+/// Systematic k-path coverage of the `.fan` grammar instead of random derivations
+#[allow(unused)]
+pub struct KPathConfig<Seeds: SeedsConfig>(PhantomData<Seeds>);
+
+impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for KPathConfig<Seeds> {
+    type Scheduler<'a> = QueueScheduler;
+
+    type EdgesObserver<'a> = super::SchedulerObserver<'a>;
+
+    fn edges_observer<'a>(observer: super::SchedulerObserver<'a>) -> Self::EdgesObserver<'a> {
+        observer
+    }
+
+    fn scheduler<'a>(_observer: &Self::EdgesObserver<'a>) -> Self::Scheduler<'a> {
+        QueueScheduler::new()
+    }
+
+    type Input = BytesInput;
+
+    fn initial_inputs(_init: &mut Self::Init, opt: &Opt) -> Vec<Self::Input> {
+        let mut generator = opt.kpath.generator(opt).unwrap();
+        let mut rand = StdRand::with_seed(current_nanos());
+        let mut inputs = (0..NUM_GENERATED)
+            .map(|_| {
+                let mut bytes = vec![];
+                generator
+                    .cfg()
+                    .unparse(&generator.derive(&mut rand), &mut bytes);
+                BytesInput::new(bytes)
+            })
+            .collect::<Vec<_>>();
+        inputs.extend(Seeds::get_seeds().into_iter().map(BytesInput::new));
+        inputs
+    }
+
+    type Init = ();
+
+    fn init() -> Self::Init {}
+
+    type Executor<'a, OT, S> = GenericExecutor<BytesInput, OT, S>;
+
+    type GrammarCoverage = GrammarCoverageFeedback;
+
+    fn grammar_coverage(feedback: GrammarCoverageFeedback) -> Self::GrammarCoverage {
+        feedback
+    }
+
+    fn get_executor<'a, OT: ObserversTuple<BytesInput, S>, S>(
+        _init: &'a mut Self::Init,
+        stdout_observer: libafl::observers::StdOutObserver,
+        stderr_observer: libafl::observers::StdErrObserver,
+        observers: OT,
+        _correctness: Handle<CorrectnessObserver>,
+        shmem_description: libafl_bolts::shmem::ShMemDescription,
+        opt: &Opt,
+    ) -> Result<Self::Executor<'a, OT, S>, Error> {
+        get_executor(
+            stdout_observer,
+            stderr_observer,
+            observers,
+            shmem_description,
+            opt.redirection_shared_library.to_str().unwrap(),
+            opt.target_binary.to_str().unwrap(),
+        )
+    }
+}
+
+/// Reports the round of the [`KPathGenerator`] and the k-paths covered in it as the
+/// `kpath-coverage` stat
+#[allow(unused)]
+pub struct KPathProgressHook;
+
+impl<E, EM, I, S> GeneratedInputHook<E, EM, KPathGenerator, I, S> for KPathProgressHook
+where
+    EM: EventFirer<I, S>,
+    S: HasExecutions + HasMetadata,
+{
+    fn evaluated(
+        &mut self,
+        _generator: &mut KPathGenerator,
+        _executor: &mut E,
+        _state: &mut S,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn report(
+        &mut self,
+        generator: &mut KPathGenerator,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let summary = generator.summary(state.metadata::<KPathProgress>()?);
+        report_string::<EM, I, S>(state, manager, Cow::Borrowed("kpath-coverage"), summary)
+    }
+}
+
+/// Continuously generates derivations covering new k-paths of the `.fan` grammar, applying
+/// `$inner` between `--post-mutations-min` and `--post-mutations-max` times to each of them
+#[allow(unused_macros)]
+macro_rules! setup_kpath_stages {
+    ($opt:expr, $inner:expr) => {{
+        tuple_list!(crate::stages::GeneratorPostMutationalStage::new(
+            $opt.kpath.generator($opt).unwrap(),
+            $inner,
+            $opt.post_mutations_min,
+            $opt.post_mutations_max
+        )
+        .with_hook(crate::config::kpath::KPathProgressHook))
+    }};
+}
@@ -1,20 +1,38 @@
 #[macro_use]
+pub mod fan;
+#[macro_use]
 pub mod fandango;
+#[cfg(feature = "fandango")]
+pub mod fandango_pool;
+#[macro_use]
+pub mod gramatron;
+#[macro_use]
+pub mod grimoire;
+#[macro_use]
+pub mod kpath;
 #[macro_use]
 pub mod nautilus;
+#[macro_use]
+pub mod nautilus_tree;
 pub mod seeds;
 
 #[allow(unused_imports)]
-pub use {fandango::FandangoConfig, nautilus::NautilusConfig};
+pub use {
+    fan::FanConfig, fandango::FandangoConfig, gramatron::GramatronConfig, grimoire::GrimoireConfig,
+    kpath::KPathConfig, nautilus::NautilusConfig, nautilus_tree::NautilusTreeConfig,
+};
 
 use libafl::{
     inputs::BytesInput,
     observers::{ObserversTuple, StdErrObserver, StdOutObserver},
     Error,
 };
-use libafl_bolts::shmem::ShMemDescription;
+use libafl_bolts::{shmem::ShMemDescription, tuples::Handle};
 
-use crate::{config::seeds::SeedsConfig, Opt};
+use crate::{
+    config::seeds::SeedsConfig, feedback::GrammarCoverageFeedback, observer::CorrectnessObserver,
+    Opt,
+};
 
 pub type SchedulerObserver<'a> = libafl::observers::ExplicitTracking<
     libafl::observers::HitcountsMapObserver<libafl::observers::StdMapObserver<'a, u8, false>>,
@@ -24,19 +42,26 @@ pub type SchedulerObserver<'a> = libafl::observers::ExplicitTracking<
 
 pub trait FuzzerConfig<Seeds: SeedsConfig> {
     type Scheduler<'a>;
+    /// The edges observer, tracking whatever the config's scheduler and stages need
+    type EdgesObserver<'a>;
     type Input;
     type Init;
     type Executor<'a, OT, S>;
-    fn scheduler<'a>(observer: &SchedulerObserver<'a>) -> Self::Scheduler<'a>;
+    /// The `feedback` for configs that generate from a grammar, one that is never interesting
+    /// for the others
+    type GrammarCoverage;
+    fn edges_observer<'a>(observer: SchedulerObserver<'a>) -> Self::EdgesObserver<'a>;
+    fn scheduler<'a>(observer: &Self::EdgesObserver<'a>) -> Self::Scheduler<'a>;
     fn initial_inputs(init: &mut Self::Init, opt: &Opt) -> Vec<Self::Input>;
     fn init() -> Self::Init;
+    fn grammar_coverage(feedback: GrammarCoverageFeedback) -> Self::GrammarCoverage;
     fn get_executor<'a, OT: ObserversTuple<BytesInput, S>, S>(
         init: &'a mut Self::Init,
         stdout_observer: StdOutObserver,
         stderr_observer: StdErrObserver,
         observers: OT,
+        correctness: Handle<CorrectnessObserver>,
         shmem_description: ShMemDescription,
-        redirection_shared_library: &str,
-        target_binary: &str,
+        opt: &Opt,
     ) -> Result<Self::Executor<'a, OT, S>, Error>;
 }
@@ -1,11 +1,12 @@
-use std::{fs, marker::PhantomData};
+use std::{fs, marker::PhantomData, path::PathBuf};
 
+use clap::Args;
 use libafl::{
-    executors::{Executor, HasObservers},
-    generators::{Generator as _, NautilusContext, NautilusGenerator},
+    common::nautilus::grammartec::{newtypes::RuleId, rule::RuleIdOrCustom, tree::Tree},
+    generators::{Generator, NautilusContext, NautilusGenerator},
     inputs::{
-        BytesInput, EncodedInput, InputDecoder as _, InputEncoder as _, NaiveTokenizer,
-        NautilusInput, TokenInputEncoderDecoder,
+        BytesInput, EncodedInput, InputDecoder as _, InputEncoder as _, NautilusInput,
+        TokenInputEncoderDecoder,
     },
     mutators::{
         EncodedAddMutator, EncodedCopyMutator, EncodedCrossoverInsertMutator,
@@ -14,17 +15,220 @@ use libafl::{
     },
     observers::ObserversTuple,
     schedulers::{IndexesLenTimeMinimizerScheduler, QueueScheduler},
-    state::NopState,
-    Error,
+    state::{HasRand, NopState},
+    Error, HasMetadata,
 };
-use libafl_bolts::tuples::{tuple_list_type, RefIndexable};
+use libafl_bolts::{
+    current_nanos,
+    rands::{Rand, StdRand},
+    tuples::{tuple_list_type, Handle},
+    SerdeAny,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     config::{seeds::SeedsConfig, FuzzerConfig},
-    executor::{get_executor, GenericExecutor},
-    Opt, NUM_GENERATED,
+    executor::{get_executor, GenericExecutor, Terminator, Unparser, UnparsingExecutor},
+    feedback::GrammarCoverageFeedback,
+    grammar::{
+        self, earley::Recognizer, generator::CfgGenerator, weights, Cfg, Derivation,
+        RuleId as CfgRuleId,
+    },
+    observer::{CorrectnessObserver, STEP_LEXING, STEP_PARSING, STEP_UNKNOWN, STEP_VALID},
+    stages::{adaptive::AdaptiveGenerator, blame::HasDerivations},
+    tokenizer::{strip_preprocessor, CTokenizer},
+    Opt, NAUTILUS_MAX_DEPTH, NUM_GENERATED,
 };
 
+/// Nonterminal of whole programs in `c.json`, whose first rule is only `PRIMARYEXPRESSION1`
+pub const PROGRAM_START: &str = "TRANSLATIONUNIT";
+
+#[derive(Debug, Clone, Args)]
+pub struct NautilusOpt {
+    #[arg(
+        long = "nautilus-start",
+        help = "Start Nautilus derivations at this nonterminal instead of the one of the first rule",
+        name = "NAUTILUS_START"
+    )]
+    start: Option<String>,
+
+    #[arg(
+        long = "nautilus-weights",
+        help = "Generate Nautilus trees with the rule probabilities from this file (see learn-weights), within --fan-max-depth and --fan-max-size",
+        name = "NAUTILUS_WEIGHTS"
+    )]
+    weights: Option<PathBuf>,
+
+    #[arg(
+        long = "nautilus-adapt-rate",
+        help = "Set by how much setup_nautilus_adaptive_stages! scales the weights of rules in inputs that pass or fail parsing, default is 0.05",
+        name = "NAUTILUS_ADAPT_RATE",
+        default_value = "0.05"
+    )]
+    adapt_rate: f64,
+}
+
+impl NautilusOpt {
+    /// These options, but starting at `start` unless `--nautilus-start` is given
+    pub fn or_start(&self, start: &str) -> Self {
+        Self {
+            start: Some(self.start.clone().unwrap_or_else(|| start.to_string())),
+            ..self.clone()
+        }
+    }
+
+    /// The Nautilus grammar given by `opt`, as a [`Cfg`] and a context with the same rule ids
+    pub fn grammar(&self, opt: &Opt) -> Result<(Cfg, NautilusContext), Error> {
+        let mut cfg = grammar::nautilus::from_file(format!("{}.json", opt.grammar_file_prefix))?;
+        if let Some(start) = &self.start {
+            cfg.start = cfg.nonterminal_id(start).ok_or_else(|| {
+                Error::illegal_argument(format!("No nonterminal {start} in the Nautilus grammar"))
+            })?;
+        }
+        // `NautilusContext` starts at the first rule and numbers the rules in order
+        let rules = grammar::nautilus::to_rules(&cfg);
+        let cfg = grammar::nautilus::parse(&rules)?;
+        let rules = rules
+            .iter()
+            .map(|(nt, format)| vec![nt.as_str(), format.as_str()])
+            .collect::<Vec<_>>();
+        Ok((cfg, NautilusContext::new(NAUTILUS_MAX_DEPTH, &rules)))
+    }
+
+    pub fn context(&self, opt: &Opt) -> Result<NautilusContext, Error> {
+        Ok(self.grammar(opt)?.1)
+    }
+
+    pub fn tree_generator(&self, opt: &Opt) -> Result<NautilusTreeGenerator, Error> {
+        let (cfg, context) = self.grammar(opt)?;
+        let weighted = match &self.weights {
+            Some(path) => {
+                let weights = weights::load(&cfg, path)?;
+                Some(opt.fan.generator_for(cfg.clone())?.with_weights(weights))
+            }
+            None => None,
+        };
+        Ok(NautilusTreeGenerator {
+            cfg,
+            context,
+            weighted,
+        })
+    }
+}
+
+impl NautilusOpt {
+    /// An [`AdaptiveNautilusGenerator`] that writes its weights to `weights_file`
+    pub fn adaptive_generator(
+        &self,
+        opt: &Opt,
+        weights_file: PathBuf,
+    ) -> Result<AdaptiveNautilusGenerator, Error> {
+        let (cfg, context) = self.grammar(opt)?;
+        let weights = match &self.weights {
+            Some(path) => weights::load(&cfg, path)?,
+            None => vec![1.0; cfg.rules.len()],
+        };
+        let generator = opt.fan.generator_for(cfg.clone())?.with_weights(weights);
+        Ok(AdaptiveNautilusGenerator {
+            cfg,
+            context,
+            generator,
+            restored: false,
+            last_rules: vec![],
+            rate: self.adapt_rate,
+            weights_file,
+        })
+    }
+}
+
+/// Turns a derivation of the [`Cfg`] from [`NautilusOpt::grammar`] into a Nautilus tree
+pub fn to_tree(cfg: &Cfg, context: &NautilusContext, derivation: &Derivation) -> NautilusInput {
+    // The context adds its `START` rule after the grammar's rules
+    let start = RuleIdOrCustom::Rule(RuleId::from(cfg.rules.len()));
+    let rules = core::iter::once(start)
+        .chain(
+            derivation
+                .rules()
+                .into_iter()
+                .map(|rule| RuleIdOrCustom::Rule(RuleId::from(rule))),
+        )
+        .collect();
+    NautilusInput::new(Tree::from_rule_vec(rules, &context.ctx))
+}
+
+/// Generates Nautilus trees, with Nautilus' own generator or, given rule weights, by deriving
+/// from the grammar with a weighted [`CfgGenerator`]
+pub struct NautilusTreeGenerator {
+    cfg: Cfg,
+    context: NautilusContext,
+    weighted: Option<CfgGenerator>,
+}
+
+impl NautilusTreeGenerator {
+    /// The grammar, with the same rule ids as the context
+    pub fn cfg(&self) -> &Cfg {
+        &self.cfg
+    }
+
+    pub fn context(&self) -> &NautilusContext {
+        &self.context
+    }
+
+    pub fn into_context(self) -> NautilusContext {
+        self.context
+    }
+
+    pub fn generate_tree<R: Rand>(&self, rand: &mut R) -> Result<NautilusInput, Error> {
+        match &self.weighted {
+            Some(generator) => Ok(to_tree(&self.cfg, &self.context, &generator.derive(rand))),
+            None => NautilusGenerator::new(&self.context)
+                .generate(&mut NopState::<NautilusInput>::new()),
+        }
+    }
+}
+
+impl<S> Generator<NautilusInput, S> for NautilusTreeGenerator
+where
+    S: HasRand,
+{
+    fn generate(&mut self, state: &mut S) -> Result<NautilusInput, Error> {
+        self.generate_tree(state.rand_mut())
+    }
+}
+
+/// Parses `seeds` into Nautilus trees, reporting the seeds outside the grammar's language.
+///
+/// Directives and comments are dropped and whitespace is ignored, see [`Recognizer`].
+pub fn seed_trees(
+    cfg: &Cfg,
+    context: &NautilusContext,
+    seeds: &[Vec<u8>],
+) -> Result<Vec<NautilusInput>, Error> {
+    let recognizer = Recognizer::new(cfg, true);
+    let mut trees = vec![];
+    let mut unparsed = vec![];
+    for (i, seed) in seeds.iter().enumerate() {
+        match recognizer.parse(&strip_preprocessor(seed)) {
+            Some(derivation) => trees.push(to_tree(cfg, context, &derivation)),
+            None => unparsed.push(i),
+        }
+    }
+    println!(
+        "Parsed {} of {} seeds into Nautilus trees",
+        trees.len(),
+        seeds.len()
+    );
+    if !seeds.is_empty() && trees.is_empty() {
+        return Err(Error::illegal_argument(
+            "None of the seeds parse under the Nautilus grammar, check --nautilus-start",
+        ));
+    }
+    if !unparsed.is_empty() {
+        println!("Seeds outside the Nautilus grammar: {unparsed:?}");
+    }
+    Ok(trees)
+}
+
 #[allow(unused)]
 pub struct NautilusConfig<Seeds: SeedsConfig>(PhantomData<Seeds>);
 
@@ -57,30 +261,33 @@ impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for NautilusConfig<Seeds> {
         SchedulerObserver<'a>,
     >;
 
-    fn scheduler<'a>(observer: &SchedulerObserver<'a>) -> Self::Scheduler<'a> {
+    type EdgesObserver<'a> = SchedulerObserver<'a>;
+
+    fn edges_observer<'a>(observer: SchedulerObserver<'a>) -> Self::EdgesObserver<'a> {
+        observer
+    }
+
+    fn scheduler<'a>(observer: &Self::EdgesObserver<'a>) -> Self::Scheduler<'a> {
         IndexesLenTimeMinimizerScheduler::new(observer, QueueScheduler::new())
     }
 
     type Input = EncodedInput;
 
     fn initial_inputs(init: &mut Self::Init, opt: &Opt) -> Vec<Self::Input> {
-        let (ref mut _bytes, ref mut encoder_decoder) = init;
+        let encoder_decoder = init;
         let mut initial_dir = opt.output.clone();
         initial_dir.push("initial");
         fs::create_dir_all(&initial_dir).unwrap();
 
-        let context =
-            NautilusContext::from_file(256, format!("{}.json", opt.grammar_file_prefix)).unwrap();
-        let mut tokenizer = NaiveTokenizer::default();
+        let generator = opt.nautilus.tree_generator(opt).unwrap();
+        let mut rand = StdRand::with_seed(current_nanos());
+        let mut tokenizer = CTokenizer;
         let mut initial_inputs = vec![];
-        let mut generator = NautilusGenerator::new(&context);
 
         let mut bytes = vec![];
         for _i in 0..NUM_GENERATED {
-            let nautilus = generator
-                .generate(&mut NopState::<NautilusInput>::new())
-                .unwrap();
-            nautilus.unparse(&context, &mut bytes);
+            let nautilus = generator.generate_tree(&mut rand).unwrap();
+            nautilus.unparse(generator.context(), &mut bytes);
 
             // let mut file = fs::File::create(initial_dir.join(format!("id_{i}"))).unwrap();
             // file.write_all(&bytes).unwrap();
@@ -99,93 +306,220 @@ impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for NautilusConfig<Seeds> {
         initial_inputs
     }
 
-    type Init = (Vec<u8>, TokenInputEncoderDecoder);
+    type Init = TokenInputEncoderDecoder;
 
     fn init() -> Self::Init {
-        (vec![], TokenInputEncoderDecoder::new())
+        TokenInputEncoderDecoder::new()
     }
 
     type Executor<'a, OT, S> =
-        NautilusUnparsingExecutor<'a, GenericExecutor<BytesInput, OT, S>, Seeds>;
+        UnparsingExecutor<GenericExecutor<BytesInput, OT, S>, &'a mut TokenInputEncoderDecoder>;
+
+    type GrammarCoverage = GrammarCoverageFeedback;
+
+    fn grammar_coverage(feedback: GrammarCoverageFeedback) -> Self::GrammarCoverage {
+        feedback
+    }
 
     fn get_executor<'a, OT: ObserversTuple<BytesInput, S>, S>(
         init: &'a mut Self::Init,
         stdout_observer: libafl::observers::StdOutObserver,
         stderr_observer: libafl::observers::StdErrObserver,
         observers: OT,
+        correctness: Handle<CorrectnessObserver>,
         shmem_description: libafl_bolts::shmem::ShMemDescription,
-        redirection_shared_library: &str,
-        target_binary: &str,
+        opt: &Opt,
     ) -> Result<Self::Executor<'a, OT, S>, Error> {
         let inner = get_executor(
             stdout_observer,
             stderr_observer,
             observers,
             shmem_description,
-            redirection_shared_library,
-            target_binary,
+            opt.redirection_shared_library.to_str().unwrap(),
+            opt.target_binary.to_str().unwrap(),
         )?;
-        Ok(NautilusUnparsingExecutor::new(init, inner))
+        Ok(UnparsingExecutor::new(
+            inner,
+            init,
+            correctness,
+            opt.terminator,
+        ))
     }
 }
 
+impl Unparser<EncodedInput> for &mut TokenInputEncoderDecoder {
+    fn unparse(&mut self, input: &EncodedInput, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        self.decode(input, bytes)
+    }
+}
+
+/// Generates Nautilus trees and hands them out unparsed and terminated, for configs working on
+/// [`BytesInput`]
 #[allow(unused)]
-pub struct NautilusUnparsingExecutor<'a, E, Seeds: SeedsConfig> {
-    init: &'a mut <NautilusConfig<Seeds> as FuzzerConfig<Seeds>>::Init,
-    inner: E,
+pub struct NautilusBytesGenerator {
+    generator: NautilusTreeGenerator,
+    terminator: Terminator,
+    /// Distinct rules of the last generated tree
+    last_rules: Vec<CfgRuleId>,
 }
 
-impl<'a, E, Seeds: SeedsConfig> NautilusUnparsingExecutor<'a, E, Seeds> {
+impl NautilusBytesGenerator {
     #[allow(unused)]
-    pub fn new(
-        init: &'a mut <NautilusConfig<Seeds> as FuzzerConfig<Seeds>>::Init,
-        inner: E,
-    ) -> Self {
-        Self { init, inner }
+    pub fn new(generator: NautilusTreeGenerator, terminator: Terminator) -> Self {
+        Self {
+            generator,
+            terminator,
+            last_rules: vec![],
+        }
     }
 }
 
-impl<'a, E, Seeds: SeedsConfig> HasObservers for NautilusUnparsingExecutor<'a, E, Seeds>
+impl<S> Generator<BytesInput, S> for NautilusBytesGenerator
 where
-    E: HasObservers,
+    S: HasRand,
 {
-    type Observers = E::Observers;
+    fn generate(&mut self, state: &mut S) -> Result<BytesInput, Error> {
+        let nautilus = self.generator.generate_tree(state.rand_mut())?;
+        // Leave out the context's `START` rule, which is not part of the grammar
+        let start = self.generator.cfg().rules.len();
+        self.last_rules = nautilus
+            .tree()
+            .rules
+            .iter()
+            .map(|rule| usize::from(rule.id()))
+            .filter(|&rule| rule != start)
+            .collect();
+        self.last_rules.sort_unstable();
+        self.last_rules.dedup();
+
+        let mut bytes = vec![];
+        nautilus.unparse(self.generator.context(), &mut bytes);
+        self.terminator.apply(&mut bytes);
+        Ok(BytesInput::new(bytes))
+    }
+}
 
-    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
-        self.inner.observers()
+impl HasDerivations for NautilusBytesGenerator {
+    fn cfg(&self) -> &Cfg {
+        self.generator.cfg()
     }
 
-    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
-        self.inner.observers_mut()
+    fn last_rules(&self) -> &[CfgRuleId] {
+        &self.last_rules
     }
 }
 
-impl<'a, E, EM, S, Z, Seeds: SeedsConfig> Executor<EM, EncodedInput, S, Z>
-    for NautilusUnparsingExecutor<'a, E, Seeds>
+/// Bounds of adapted rule weights, so no rule becomes impossible or dominates for good
+const MIN_WEIGHT: f64 = 0.01;
+const MAX_WEIGHT: f64 = 100.0;
+
+/// How many of the lowest and highest weighted rules to report
+const REPORTED_RULES: usize = 5;
+
+/// The rule weights of an [`AdaptiveNautilusGenerator`] and how often each rule occurred in
+/// generated derivations, kept in the state so they survive restarts
+#[derive(Debug, Serialize, Deserialize, SerdeAny)]
+pub struct AdaptiveWeightsMetadata {
+    weights: Vec<f64>,
+    uses: Vec<usize>,
+}
+
+/// Generates unparsed Nautilus trees with rule weights that follow the step the inputs reach.
+///
+/// Rules of inputs rejected by the lexer or parser are penalized, rules of inputs that get
+/// further are reinforced, and more so for valid inputs. The weights live in the
+/// [`AdaptiveWeightsMetadata`] of the state and are written to the weights file with every
+/// report, in the format of `learn-weights`.
+#[allow(unused)]
+pub struct AdaptiveNautilusGenerator {
+    cfg: Cfg,
+    context: NautilusContext,
+    /// Picks rules with a copy of the weights of the state
+    generator: CfgGenerator,
+    /// Whether the weights of a restored state were copied into `generator`
+    restored: bool,
+    /// Distinct rules of the last generated derivation
+    last_rules: Vec<CfgRuleId>,
+    rate: f64,
+    weights_file: PathBuf,
+}
+
+impl<S> Generator<BytesInput, S> for AdaptiveNautilusGenerator
 where
-    E: Executor<EM, BytesInput, S, Z>,
+    S: HasRand + HasMetadata,
 {
-    fn run_target(
-        &mut self,
-        fuzzer: &mut Z,
-        state: &mut S,
-        mgr: &mut EM,
-        input: &EncodedInput,
-    ) -> Result<libafl::executors::ExitKind, libafl::Error> {
-        let (ref mut bytes, ref mut encoder_decoder) = self.init;
-        bytes.clear();
-        encoder_decoder.decode(input, bytes).unwrap();
-        if *bytes.last().unwrap() != 0 {
-            bytes.push(0);
+    fn generate(&mut self, state: &mut S) -> Result<BytesInput, Error> {
+        if !self.restored {
+            let metadata = state.metadata_or_insert_with(|| AdaptiveWeightsMetadata {
+                weights: self.generator.weights().unwrap().to_vec(),
+                uses: vec![0; self.cfg.rules.len()],
+            });
+            *self.generator.weights_mut().unwrap() = metadata.weights.clone();
+            self.restored = true;
         }
 
-        let unparsed_input = bytes.as_slice();
-        self.inner.run_target(
-            fuzzer,
-            state,
-            mgr,
-            &BytesInput::new(unparsed_input.to_vec()),
-        )
+        let derivation = self.generator.derive(state.rand_mut());
+        let mut rules = derivation.rules();
+        let uses = &mut state.metadata_mut::<AdaptiveWeightsMetadata>()?.uses;
+        for &rule in &rules {
+            uses[rule] += 1;
+        }
+        rules.sort_unstable();
+        rules.dedup();
+        self.last_rules = rules;
+
+        let mut bytes = vec![];
+        to_tree(&self.cfg, &self.context, &derivation).unparse(&self.context, &mut bytes);
+        Ok(BytesInput::new(bytes))
+    }
+}
+
+impl<S> AdaptiveGenerator<BytesInput, S> for AdaptiveNautilusGenerator
+where
+    S: HasRand + HasMetadata,
+{
+    fn adapt(&mut self, state: &mut S, step: usize) -> Result<(), Error> {
+        let factor = match step {
+            STEP_LEXING | STEP_PARSING => 1.0 - self.rate,
+            STEP_VALID => (1.0 + self.rate).powi(2),
+            step if step > STEP_PARSING && step < STEP_UNKNOWN => 1.0 + self.rate,
+            _ => return Ok(()),
+        };
+        let weights = self.generator.weights_mut().unwrap();
+        let stored = &mut state.metadata_mut::<AdaptiveWeightsMetadata>()?.weights;
+        for &rule in &self.last_rules {
+            weights[rule] = (weights[rule] * factor).clamp(MIN_WEIGHT, MAX_WEIGHT);
+            stored[rule] = weights[rule];
+        }
+        Ok(())
+    }
+
+    fn report(&mut self, state: &mut S) -> Result<String, Error> {
+        let metadata = state.metadata::<AdaptiveWeightsMetadata>()?;
+        let probabilities = weights::normalize(&self.cfg, &metadata.weights);
+        weights::store(
+            &self.cfg,
+            &metadata.uses,
+            &probabilities,
+            &self.weights_file,
+        )?;
+
+        // Only rules with alternatives have a say in generation
+        let mut ranked = (0..self.cfg.rules.len())
+            .filter(|&r| self.cfg.rules_by_nt[self.cfg.rules[r].lhs].len() > 1)
+            .collect::<Vec<_>>();
+        ranked.sort_by(|&a, &b| probabilities[a].total_cmp(&probabilities[b]));
+        let describe = |rules: &mut dyn Iterator<Item = &CfgRuleId>| {
+            rules
+                .map(|&r| format!("{} ({:.3})", self.cfg.display_rule(r), probabilities[r]))
+                .collect::<Vec<_>>()
+                .join("; ")
+        };
+        Ok(format!(
+            "lowest: {} | highest: {}",
+            describe(&mut ranked.iter().take(REPORTED_RULES)),
+            describe(&mut ranked.iter().rev().take(REPORTED_RULES)),
+        ))
     }
 }
 
@@ -200,3 +534,64 @@ macro_rules! setup_nautilus_stages {
         ))
     };
 }
+
+/// Like `setup_nautilus_posthavoc_stages!`, but with rule weights that adapt to how far the
+/// generated inputs get in clang, see [`AdaptiveNautilusGenerator`]. The weights are written to
+/// `rule-weights-<client>.json` in the output directory.
+#[allow(unused_macros)]
+macro_rules! setup_nautilus_adaptive_stages {
+    ($opt:expr, $inner:expr, $correctness:expr, $client:expr) => {{
+        let weights_file = $opt
+            .output
+            .join(format!("rule-weights-{}.json", $client.id()));
+        tuple_list!(crate::stages::GeneratorPostMutationalStage::new(
+            $opt.nautilus
+                .adaptive_generator($opt, weights_file)
+                .unwrap(),
+            $inner,
+            $opt.post_mutations_min,
+            $opt.post_mutations_max
+        )
+        .with_hook(crate::stages::AdaptiveGeneratorHook::new($correctness)))
+    }};
+}
+
+/// Nautilus counterpart to `setup_fandango_stages!`, to be used with the byte-level
+/// `FandangoConfig`: every generated tree is unparsed, run, and then mutated with `$inner`
+/// between `--post-mutations-min` and `--post-mutations-max` times.
+#[allow(unused_macros)]
+macro_rules! setup_nautilus_posthavoc_stages {
+    ($opt:expr, $inner:expr) => {{
+        let generator = $opt.nautilus.tree_generator($opt).unwrap();
+        tuple_list!(crate::stages::GeneratorPostMutationalStage::new(
+            crate::config::nautilus::NautilusBytesGenerator::new(generator, $opt.terminator),
+            $inner,
+            $opt.post_mutations_min,
+            $opt.post_mutations_max
+        ))
+    }};
+}
+
+/// Like `setup_nautilus_posthavoc_stages!`, but blames the rules of the generated trees for the
+/// steps and diagnostics they get, see [`RuleBlameHook`](crate::stages::RuleBlameHook). The
+/// blame is written to `rule-blame-<client>.json` in the output directory.
+#[allow(unused_macros)]
+macro_rules! setup_nautilus_blame_stages {
+    ($opt:expr, $inner:expr, $correctness:expr, $stderr:expr, $client:expr) => {{
+        let generator = $opt.nautilus.tree_generator($opt).unwrap();
+        let blame_file = $opt
+            .output
+            .join(format!("rule-blame-{}.json", $client.id()));
+        tuple_list!(crate::stages::GeneratorPostMutationalStage::new(
+            crate::config::nautilus::NautilusBytesGenerator::new(generator, $opt.terminator),
+            $inner,
+            $opt.post_mutations_min,
+            $opt.post_mutations_max
+        )
+        .with_hook(crate::stages::RuleBlameHook::new(
+            $correctness,
+            $stderr,
+            blame_file
+        )))
+    }};
+}
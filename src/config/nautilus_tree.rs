@@ -0,0 +1,137 @@
+use std::marker::PhantomData;
+
+use libafl::{
+    generators::NautilusContext,
+    inputs::{BytesInput, NautilusInput},
+    observers::ObserversTuple,
+    schedulers::QueueScheduler,
+    Error,
+};
+use libafl_bolts::{current_nanos, rands::StdRand, tuples::Handle};
+
+use crate::{
+    config::{
+        nautilus::{seed_trees, PROGRAM_START},
+        seeds::SeedsConfig,
+        FuzzerConfig,
+    },
+    executor::{get_executor, GenericExecutor, Unparser, UnparsingExecutor},
+    feedback::GrammarCoverageFeedback,
+    observer::CorrectnessObserver,
+    Opt, NUM_GENERATED,
+};
+
+/// Nautilus working on its own derivation trees, seeded with generated trees and with the seeds
+/// that parse under the Nautilus grammar, so splicing can reuse subtrees of real programs.
+///
+/// Derivations start at [`PROGRAM_START`] unless `--nautilus-start` is given.
+#[allow(unused)]
+pub struct NautilusTreeConfig<Seeds: SeedsConfig>(PhantomData<Seeds>);
+
+impl<Seeds: SeedsConfig> FuzzerConfig<Seeds> for NautilusTreeConfig<Seeds> {
+    type Scheduler<'a> = QueueScheduler;
+
+    type EdgesObserver<'a> = super::SchedulerObserver<'a>;
+
+    fn edges_observer<'a>(observer: super::SchedulerObserver<'a>) -> Self::EdgesObserver<'a> {
+        observer
+    }
+
+    fn scheduler<'a>(_observer: &Self::EdgesObserver<'a>) -> Self::Scheduler<'a> {
+        QueueScheduler::new()
+    }
+
+    type Input = NautilusInput;
+
+    fn initial_inputs(init: &mut Self::Init, opt: &Opt) -> Vec<Self::Input> {
+        let generator = opt
+            .nautilus
+            .or_start(PROGRAM_START)
+            .tree_generator(opt)
+            .unwrap();
+        let mut rand = StdRand::with_seed(current_nanos());
+        let mut inputs = (0..NUM_GENERATED)
+            .map(|_| generator.generate_tree(&mut rand).unwrap())
+            .collect::<Vec<_>>();
+        inputs
+            .extend(seed_trees(generator.cfg(), generator.context(), &Seeds::get_seeds()).unwrap());
+        *init = Some(generator.into_context());
+        inputs
+    }
+
+    /// The context, loaded along with the initial inputs
+    type Init = Option<NautilusContext>;
+
+    fn init() -> Self::Init {
+        None
+    }
+
+    type Executor<'a, OT, S> =
+        UnparsingExecutor<GenericExecutor<BytesInput, OT, S>, &'a NautilusContext>;
+
+    type GrammarCoverage = GrammarCoverageFeedback;
+
+    fn grammar_coverage(feedback: GrammarCoverageFeedback) -> Self::GrammarCoverage {
+        feedback
+    }
+
+    fn get_executor<'a, OT: ObserversTuple<BytesInput, S>, S>(
+        init: &'a mut Self::Init,
+        stdout_observer: libafl::observers::StdOutObserver,
+        stderr_observer: libafl::observers::StdErrObserver,
+        observers: OT,
+        correctness: Handle<CorrectnessObserver>,
+        shmem_description: libafl_bolts::shmem::ShMemDescription,
+        opt: &Opt,
+    ) -> Result<Self::Executor<'a, OT, S>, Error> {
+        let inner = get_executor(
+            stdout_observer,
+            stderr_observer,
+            observers,
+            shmem_description,
+            opt.redirection_shared_library.to_str().unwrap(),
+            opt.target_binary.to_str().unwrap(),
+        )?;
+        let context = init
+            .as_ref()
+            .ok_or_else(|| Error::illegal_state("Nautilus context used before initial inputs"))?;
+        Ok(UnparsingExecutor::new(
+            inner,
+            context,
+            correctness,
+            opt.terminator,
+        ))
+    }
+}
+
+impl Unparser<NautilusInput> for &NautilusContext {
+    fn unparse(&mut self, input: &NautilusInput, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        input.unparse(*self, bytes);
+        Ok(())
+    }
+}
+
+/// Tree-level Nautilus mutations, including splicing subtrees of the corpus.
+///
+/// `$context` is a `NautilusContext` of the Nautilus grammar that outlives the stages.
+#[allow(unused_macros)]
+macro_rules! setup_nautilus_tree_stages {
+    ($opt:expr, $context:expr) => {{
+        let context: &libafl::generators::NautilusContext = $context;
+        tuple_list!(
+            crate::stages::NautilusChunksStage::new(context, $opt.output.join("chunks")),
+            libafl::stages::StdMutationalStage::new(
+                libafl::mutators::HavocScheduledMutator::with_max_stack_pow(
+                    tuple_list!(
+                        libafl::mutators::NautilusRandomMutator::new(context),
+                        libafl::mutators::NautilusRandomMutator::new(context),
+                        libafl::mutators::NautilusRecursionMutator::new(context),
+                        libafl::mutators::NautilusSpliceMutator::new(context),
+                        libafl::mutators::NautilusSpliceMutator::new(context),
+                    ),
+                    2
+                )
+            )
+        )
+    }};
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl::{mutators::Tokens, Error};
+
+use crate::grammar::{Cfg, Symbol};
+
+/// The terminals of `cfg` that make useful tokens, leaving out whitespace and the single
+/// letters and digits identifiers and constants are spelled with
+pub fn grammar_tokens(cfg: &Cfg) -> BTreeSet<Vec<u8>> {
+    cfg.rules
+        .iter()
+        .flat_map(|rule| &rule.rhs)
+        .filter_map(|symbol| match symbol {
+            Symbol::Terminal(t) => Some(t),
+            Symbol::NonTerminal(_) => None,
+        })
+        .filter(|t| !t.iter().all(u8::is_ascii_whitespace))
+        .filter(|t| !(t.len() == 1 && t[0].is_ascii_alphanumeric()))
+        .cloned()
+        .collect()
+}
+
+/// The keywords, punctuators and builtin traits clang's lexer knows, read from `TokenKinds.def`.
+///
+/// Preprocessor keywords get their `#`, Objective-C `@` keywords their `@`; annotations and
+/// token kinds without spelling are left out.
+pub fn clang_tokens(token_kinds: &Path) -> Result<BTreeSet<Vec<u8>>, Error> {
+    let def = fs::read_to_string(token_kinds)?;
+    let mut tokens = BTreeSet::new();
+    for line in def.lines().map(str::trim) {
+        let Some((kind, args)) = line.split_once('(') else {
+            continue;
+        };
+        if kind.is_empty()
+            || !kind
+                .bytes()
+                .all(|b| b.is_ascii_uppercase() || b == b'_' || b.is_ascii_digit())
+        {
+            continue;
+        }
+        let Some(args) = args.rfind(')').map(|end| &args[..end]) else {
+            continue;
+        };
+        let first = args.split(',').next().unwrap_or_default().trim();
+        let unquote = |arg: &str| {
+            arg.strip_prefix('"')
+                .and_then(|arg| arg.strip_suffix('"'))
+                .map(str::to_string)
+        };
+        let token = match kind {
+            // The spelling may be a comma itself
+            "PUNCTUATOR" => args
+                .split_once(',')
+                .and_then(|(_, spelling)| unquote(spelling.trim())),
+            "ALIAS" => unquote(first),
+            "PPKEYWORD" => Some(format!("#{first}")),
+            "OBJC_AT_KEYWORD" => Some(format!("@{first}")),
+            _ if kind.contains("KEYWORD") || kind.contains("TRAIT") => Some(first.to_string()),
+            _ => None,
+        };
+        if let Some(token) = token.filter(|token| !token.is_empty()) {
+            tokens.insert(token.into_bytes());
+        }
+    }
+    if tokens.is_empty() {
+        return Err(Error::illegal_argument(format!(
+            "No tokens found in {}",
+            token_kinds.display()
+        )));
+    }
+    Ok(tokens)
+}
+
+/// Builds the dictionary from the terminals of the grammars and clang's token kinds, skipping
+/// and reporting sources that cannot be read
+pub fn build(grammars: &[PathBuf], token_kinds: &Path) -> Tokens {
+    let mut tokens = BTreeSet::new();
+    for path in grammars {
+        match crate::grammar::load(path) {
+            Ok(cfg) => tokens.extend(grammar_tokens(&cfg)),
+            Err(e) => println!("Skipping {} for the dictionary: {e}", path.display()),
+        }
+    }
+    match clang_tokens(token_kinds) {
+        Ok(clang) => tokens.extend(clang),
+        Err(e) => println!("Skipping {} for the dictionary: {e}", token_kinds.display()),
+    }
+    Tokens::from(tokens.into_iter().collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use libafl::{
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasTargetBytes as _},
+        mutators::{MutationResult, Mutator, TokenInsert},
+        state::StdState,
+        HasMetadata as _,
+    };
+    use libafl_bolts::rands::StdRand;
+
+    use super::build;
+    use crate::{
+        mutators::identifiers::KEYWORDS,
+        tokenizer::{lex, TokenKind},
+    };
+
+    fn keywords(input: &[u8]) -> Vec<Vec<u8>> {
+        lex(input)
+            .into_iter()
+            .filter(|token| token.kind == TokenKind::Identifier)
+            .map(|token| input[token.start..token.end].to_vec())
+            .filter(|text| KEYWORDS.contains(&text.as_slice()))
+            .collect()
+    }
+
+    #[test]
+    fn grammar_keywords_are_tokens() {
+        let tokens = build(
+            &[PathBuf::from("c.fan"), PathBuf::from("c.json")],
+            Path::new("missing/TokenKinds.def"),
+        );
+        for keyword in [&b"while"[..], b"struct", b"return", b"typedef"] {
+            assert!(
+                tokens.iter().any(|token| token == keyword),
+                "{} missing",
+                String::from_utf8_lossy(keyword)
+            );
+        }
+    }
+
+    #[test]
+    fn token_insert_adds_keywords() {
+        let tokens = build(
+            &[PathBuf::from("c.fan"), PathBuf::from("c.json")],
+            Path::new("missing/TokenKinds.def"),
+        );
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        state.add_metadata(tokens);
+
+        let original = b"x = 1 ; y = 2 ;";
+        let mut mutator = TokenInsert::new();
+        let inserted = (0..100).any(|_| {
+            let mut input = BytesInput::new(original.to_vec());
+            let result = mutator.mutate(&mut state, &mut input).unwrap();
+            result == MutationResult::Mutated && !keywords(&input.target_bytes()).is_empty()
+        });
+        assert!(inserted, "no keyword inserted in 100 mutations");
+    }
+}
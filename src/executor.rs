@@ -1,12 +1,16 @@
 use std::{
+    borrow::Cow,
     path::Path,
     process::{Child, Command},
     time::Duration,
 };
 
 use libafl::{
-    executors::{command::StdCommandConfigurator, CommandExecutor, StdChildArgs},
-    inputs::HasTargetBytes,
+    executors::{
+        command::StdCommandConfigurator, CommandExecutor, Executor, ExitKind, HasObservers,
+        StdChildArgs,
+    },
+    inputs::{BytesInput, HasTargetBytes},
     observers::{ObserversTuple, StdErrObserver, StdOutObserver},
     Error,
 };
@@ -14,10 +18,134 @@ use libafl::{
 use libafl_bolts::{
     merge_tuple_list_type,
     shmem::ShMemDescription,
-    tuples::{tuple_list, tuple_list_type, Handled as _, Merge as _},
+    tuples::{
+        tuple_list, tuple_list_type, Handle, Handled as _, MatchName, Merge as _, RefIndexable,
+    },
     StdTargetArgs,
 };
 
+use crate::observer::{
+    CorrectnessObserver, GrammarConformanceObserver, CONFORMANCE_OBSERVER, STEP_DECODING_FAILED,
+    STEP_EMPTY_INPUT,
+};
+
+/// What to append to an unparsed input before handing it to the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Terminator {
+    /// Pass the bytes as they are
+    None,
+    /// Append a NUL byte unless the input already ends in one
+    Nul,
+    /// Append a newline unless the input already ends in one
+    Newline,
+}
+
+impl Terminator {
+    pub fn apply(self, bytes: &mut Vec<u8>) {
+        let terminator = match self {
+            Terminator::None => return,
+            Terminator::Nul => 0,
+            Terminator::Newline => b'\n',
+        };
+        if bytes.last() != Some(&terminator) {
+            bytes.push(terminator);
+        }
+    }
+}
+
+/// Turns the inputs of a config into the bytes the target reads
+pub trait Unparser<I> {
+    /// Appends the bytes of `input` to `bytes`, fails for inputs that have no bytes
+    fn unparse(&mut self, input: &I, bytes: &mut Vec<u8>) -> Result<(), Error>;
+}
+
+/// Runs the inputs of a config that does not work on bytes with an executor that does.
+///
+/// Inputs that cannot be unparsed or unparse to nothing are not run, but still counted with
+/// `STEP_DECODING_FAILED` or `STEP_EMPTY_INPUT` as their step. The unparsed bytes are also
+/// what the [`GrammarConformanceObserver`] checks.
+#[allow(unused)]
+pub struct UnparsingExecutor<E, U> {
+    inner: E,
+    unparser: U,
+    correctness: Handle<CorrectnessObserver>,
+    conformance: Handle<GrammarConformanceObserver>,
+    terminator: Terminator,
+    bytes: Vec<u8>,
+}
+
+impl<E, U> UnparsingExecutor<E, U> {
+    #[allow(unused)]
+    pub fn new(
+        inner: E,
+        unparser: U,
+        correctness: Handle<CorrectnessObserver>,
+        terminator: Terminator,
+    ) -> Self {
+        Self {
+            inner,
+            unparser,
+            correctness,
+            conformance: Handle::new(Cow::Borrowed(CONFORMANCE_OBSERVER)),
+            terminator,
+            bytes: vec![],
+        }
+    }
+}
+
+impl<E, U> HasObservers for UnparsingExecutor<E, U>
+where
+    E: HasObservers,
+{
+    type Observers = E::Observers;
+
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.inner.observers()
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.inner.observers_mut()
+    }
+}
+
+impl<E, EM, I, S, U, Z> Executor<EM, I, S, Z> for UnparsingExecutor<E, U>
+where
+    E: Executor<EM, BytesInput, S, Z> + HasObservers,
+    E::Observers: MatchName,
+    U: Unparser<I>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        self.bytes.clear();
+
+        // Don't bother the target with inputs we can't produce, but still count them
+        let skipped_step = if self.unparser.unparse(input, &mut self.bytes).is_err() {
+            Some(STEP_DECODING_FAILED)
+        } else if self.bytes.is_empty() {
+            Some(STEP_EMPTY_INPUT)
+        } else {
+            None
+        };
+        if let Some(step) = skipped_step {
+            let mut observers = self.inner.observers_mut();
+            observers[&self.correctness].force_step(step);
+            return Ok(ExitKind::Ok);
+        }
+
+        // The conformance of inputs that are not bytes themselves is only known from here on
+        self.inner.observers_mut()[&self.conformance].observe_unparsed(&self.bytes);
+
+        self.terminator.apply(&mut self.bytes);
+        self.inner
+            .run_target(fuzzer, state, mgr, &BytesInput::new(self.bytes.clone()))
+    }
+}
+
 pub type GenericExecutor<I, OT, S> = CommandExecutor<
     Child,
     (),
@@ -1,11 +1,16 @@
-use std::{borrow::Cow, collections::BTreeMap, marker::PhantomData};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    marker::PhantomData,
+};
 
 use libafl::{
+    corpus::Testcase,
     events::{Event, EventFirer, EventWithStats},
     feedbacks::{Feedback, StateInitializer},
     monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
     state::HasExecutions,
-    Error, HasNamedMetadata,
+    Error, HasMetadata as _, HasNamedMetadata,
 };
 use libafl_bolts::{
     tuples::{Handle, Handled as _, MatchName, MatchNameRef as _},
@@ -13,7 +18,52 @@ use libafl_bolts::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::observer::CorrectnessObserver;
+use crate::{
+    grammar::RuleId,
+    observer::{Conformance, CorrectnessObserver, GrammarConformanceObserver},
+};
+
+/// Fires a user stat
+pub fn report_stat<EM, I, S>(
+    state: &mut S,
+    manager: &mut EM,
+    name: Cow<'static, str>,
+    value: UserStats,
+) -> Result<(), Error>
+where
+    EM: EventFirer<I, S>,
+    S: HasExecutions,
+{
+    manager.fire(
+        state,
+        EventWithStats::with_current_time(
+            Event::UpdateUserStats {
+                name,
+                value,
+                phantom: PhantomData,
+            },
+            *state.executions(),
+        ),
+    )
+}
+
+/// Fires a string-valued user stat
+pub fn report_string<EM, I, S>(
+    state: &mut S,
+    manager: &mut EM,
+    name: Cow<'static, str>,
+    value: String,
+) -> Result<(), Error>
+where
+    EM: EventFirer<I, S>,
+    S: HasExecutions,
+{
+    let value = UserStats::new(
+        UserStatsValue::String(Cow::Owned(value)),
+        AggregatorOps::Avg,
+    );
+    report_stat::<EM, I, S>(state, manager, name, value)
+}
 
 pub struct ReportCorrectnessFeedback {
     observer: Handle<CorrectnessObserver>,
@@ -27,6 +77,12 @@ impl ReportCorrectnessFeedback {
     }
 }
 
+/// The correctness step a corpus entry reached when it was added
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, SerdeAny)]
+pub struct CorrectnessStepMetadata {
+    pub step: usize,
+}
+
 #[derive(Debug, Serialize, Deserialize, SerdeAny)]
 struct CorrectnessMetadata {
     counts: BTreeMap<usize, usize>,
@@ -92,39 +148,38 @@ where
             .intersperse(", ".to_string())
             .collect::<String>();
         if report_relative {
-            manager.fire(
+            report_string::<EM, I, S>(
                 state,
-                EventWithStats::with_current_time(
-                    Event::UpdateUserStats {
-                        name: Cow::Owned(format!("{}-relative", self.name())),
-                        value: UserStats::new(
-                            UserStatsValue::String(Cow::Owned(stringified_relative)),
-                            AggregatorOps::Avg,
-                        ),
-                        phantom: PhantomData,
-                    },
-                    *state.executions(),
-                ),
+                manager,
+                Cow::Owned(format!("{}-relative", self.name())),
+                stringified_relative,
             )?;
         }
         if report_absolute {
-            manager.fire(
+            report_string::<EM, I, S>(
                 state,
-                EventWithStats::with_current_time(
-                    Event::UpdateUserStats {
-                        name: Cow::Owned(format!("{}-absolute", self.name())),
-                        value: UserStats::new(
-                            UserStatsValue::String(Cow::Owned(stringified_absolute)),
-                            AggregatorOps::Avg,
-                        ),
-                        phantom: PhantomData,
-                    },
-                    *state.executions(),
-                ),
+                manager,
+                Cow::Owned(format!("{}-absolute", self.name())),
+                stringified_absolute,
             )?;
         }
         Ok(false)
     }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        if let Some(observer) = observers.get(&self.observer) {
+            testcase.add_metadata(CorrectnessStepMetadata {
+                step: observer.step(),
+            });
+        }
+        Ok(())
+    }
 }
 
 impl Named for ReportCorrectnessFeedback {
@@ -141,3 +196,215 @@ where
         state.add_named_metadata_checked(self.name(), CorrectnessMetadata::new())
     }
 }
+
+/// Reports how often inputs in and out of the grammar reach each correctness step, as a 2×N
+/// table in the `conformance-absolute` stat with keys `in-<step>` and `out-<step>`
+pub struct ReportConformanceFeedback {
+    correctness: Handle<CorrectnessObserver>,
+    conformance: Handle<GrammarConformanceObserver>,
+}
+
+impl ReportConformanceFeedback {
+    pub fn new(
+        correctness: &CorrectnessObserver,
+        conformance: &GrammarConformanceObserver,
+    ) -> Self {
+        Self {
+            correctness: correctness.handle(),
+            conformance: conformance.handle(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, SerdeAny)]
+struct ConformanceMetadata {
+    /// Counts by whether the input is in the grammar and by step
+    counts: BTreeMap<(bool, usize), usize>,
+    classified: usize,
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for ReportConformanceFeedback
+where
+    EM: EventFirer<I, S>,
+    S: HasExecutions + HasNamedMetadata,
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &libafl::executors::ExitKind,
+    ) -> Result<bool, Error> {
+        let conformance = observers.get(&self.conformance).ok_or_else(|| {
+            Error::illegal_state(format!("Observer {} not found", self.conformance.name()))
+        })?;
+        let in_grammar = match conformance.conformance() {
+            Conformance::Unknown => return Ok(false),
+            Conformance::InGrammar => true,
+            Conformance::OutOfGrammar => false,
+        };
+        let correctness = observers.get(&self.correctness).ok_or_else(|| {
+            Error::illegal_state(format!("Observer {} not found", self.correctness.name()))
+        })?;
+
+        let metadata =
+            state.named_metadata_or_insert_with(self.name(), ConformanceMetadata::default);
+        *metadata
+            .counts
+            .entry((in_grammar, correctness.step()))
+            .or_insert(0) += 1;
+        metadata.classified += 1;
+
+        if metadata.classified % 100 == 0 {
+            let stringified = metadata
+                .counts
+                .iter()
+                .map(|((in_grammar, step), count)| {
+                    let prefix = if *in_grammar { "in" } else { "out" };
+                    format!("{prefix}-{step}: {count}")
+                })
+                .intersperse(", ".to_string())
+                .collect::<String>();
+            report_string::<EM, I, S>(
+                state,
+                manager,
+                Cow::Owned(format!("{}-absolute", self.name())),
+                stringified,
+            )?;
+        }
+        Ok(false)
+    }
+}
+
+impl Named for ReportConformanceFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("conformance")
+    }
+}
+
+impl<S> StateInitializer<S> for ReportConformanceFeedback
+where
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.add_named_metadata_checked(self.name(), ConformanceMetadata::default())
+    }
+}
+
+/// Executions between two reports of the `grammar-coverage` stat
+pub const COVERAGE_REPORT_INTERVAL: u64 = 1000;
+
+/// Tracks which rules and k-paths of the conformance grammar the executed inputs cover, see
+/// [`GrammarConformanceObserver::with_coverage`], and reports them in the `grammar-coverage`
+/// stat as `rules: <covered>/<total>, <k>-paths: <covered>/<total>`.
+///
+/// The coverage is updated for every executed input that parses and reported every
+/// [`COVERAGE_REPORT_INTERVAL`] executions. When `guide` is set, inputs covering a new k-path
+/// are interesting.
+pub struct GrammarCoverageFeedback {
+    conformance: Handle<GrammarConformanceObserver>,
+    k: usize,
+    total_rules: usize,
+    total_paths: usize,
+    guide: bool,
+}
+
+impl GrammarCoverageFeedback {
+    pub fn new(
+        conformance: &GrammarConformanceObserver,
+        k: usize,
+        total_rules: usize,
+        total_paths: usize,
+        guide: bool,
+    ) -> Self {
+        Self {
+            conformance: conformance.handle(),
+            k,
+            total_rules,
+            total_paths,
+            guide,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, SerdeAny)]
+struct GrammarCoverageMetadata {
+    rules: HashSet<RuleId>,
+    paths: HashSet<Vec<RuleId>>,
+    parsed: usize,
+}
+
+impl GrammarCoverageFeedback {
+    fn observer<'a, OT: MatchName>(
+        &self,
+        observers: &'a OT,
+    ) -> Result<&'a GrammarConformanceObserver, Error> {
+        observers.get(&self.conformance).ok_or_else(|| {
+            Error::illegal_state(format!("Observer {} not found", self.conformance.name()))
+        })
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for GrammarCoverageFeedback
+where
+    EM: EventFirer<I, S>,
+    S: HasExecutions + HasNamedMetadata,
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &libafl::executors::ExitKind,
+    ) -> Result<bool, Error> {
+        let conformance = self.observer(observers)?;
+        let metadata =
+            state.named_metadata_or_insert_with(self.name(), GrammarCoverageMetadata::default);
+        let mut new_path = false;
+        if !conformance.rules().is_empty() {
+            metadata.rules.extend(conformance.rules());
+            for path in conformance.paths() {
+                new_path |= metadata.paths.insert(path.clone());
+            }
+            metadata.parsed += 1;
+        }
+
+        if *state.executions() % COVERAGE_REPORT_INTERVAL == 0 {
+            let metadata = state.named_metadata::<GrammarCoverageMetadata>(self.name())?;
+            let stringified = format!(
+                "rules: {}/{}, {}-paths: {}/{}",
+                metadata.rules.len(),
+                self.total_rules,
+                self.k,
+                metadata.paths.len(),
+                self.total_paths
+            );
+            report_string::<EM, I, S>(
+                state,
+                manager,
+                Cow::Borrowed("grammar-coverage"),
+                stringified,
+            )?;
+        }
+        Ok(self.guide && new_path)
+    }
+}
+
+impl Named for GrammarCoverageFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("grammar-coverage")
+    }
+}
+
+impl<S> StateInitializer<S> for GrammarCoverageFeedback
+where
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.add_named_metadata_checked(self.name(), GrammarCoverageMetadata::default())
+    }
+}
@@ -0,0 +1,34 @@
+use libafl_bolts::rands::Rand;
+
+use crate::grammar::{earley::Recognizer, generator::CfgGenerator};
+
+/// Sampled derivations of one grammar checked against the language of another
+#[derive(Debug, Default)]
+pub struct Difference {
+    pub sampled: usize,
+    /// Samples longer than the length limit, which were not checked
+    pub skipped: usize,
+    pub rejected: Vec<Vec<u8>>,
+}
+
+/// Samples `samples` derivations from `from` and collects those that `by` does not recognize
+pub fn sample_rejections<R: Rand>(
+    from: &CfgGenerator,
+    by: &Recognizer,
+    samples: usize,
+    max_len: usize,
+    rand: &mut R,
+) -> Difference {
+    let mut difference = Difference::default();
+    for _ in 0..samples {
+        let mut bytes = vec![];
+        from.cfg().unparse(&from.derive(rand), &mut bytes);
+        difference.sampled += 1;
+        if bytes.len() > max_len {
+            difference.skipped += 1;
+        } else if !by.recognizes(&bytes) {
+            difference.rejected.push(bytes);
+        }
+    }
+    difference
+}
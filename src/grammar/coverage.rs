@@ -0,0 +1,69 @@
+//! Grammar coverage in terms of k-paths: sequences of k rules in which each rule expands a
+//! nonterminal of the previous one, i.e. the rules along a downward path of length k in a
+//! derivation tree. 1-paths are the rules themselves.
+
+use crate::grammar::{Cfg, Derivation, RuleId, Symbol};
+
+/// All k-paths of `derivation`, with duplicates
+pub fn k_paths(derivation: &Derivation, k: usize) -> Vec<Vec<RuleId>> {
+    let mut paths = vec![];
+    let mut ancestors = vec![];
+    collect(derivation, k, &mut ancestors, &mut paths);
+    paths
+}
+
+fn collect(
+    derivation: &Derivation,
+    k: usize,
+    ancestors: &mut Vec<RuleId>,
+    paths: &mut Vec<Vec<RuleId>>,
+) {
+    ancestors.push(derivation.rule);
+    if ancestors.len() >= k {
+        paths.push(ancestors[ancestors.len() - k..].to_vec());
+    }
+    for child in &derivation.children {
+        collect(child, k, ancestors, paths);
+    }
+    ancestors.pop();
+}
+
+/// The number of k-paths in the grammar, including those no derivation from the start symbol
+/// can contain, as an upper bound for coverage
+pub fn count_k_paths(cfg: &Cfg, k: usize) -> usize {
+    // Rules that can expand a nonterminal of each rule, each nonterminal counted once
+    let successors = cfg
+        .rules
+        .iter()
+        .map(|rule| {
+            let mut nts = rule
+                .rhs
+                .iter()
+                .filter_map(|symbol| match symbol {
+                    Symbol::NonTerminal(nt) => Some(*nt),
+                    Symbol::Terminal(_) => None,
+                })
+                .collect::<Vec<_>>();
+            nts.sort_unstable();
+            nts.dedup();
+            nts.into_iter()
+                .flat_map(|nt| cfg.rules_by_nt[nt].iter().copied())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    // Number of paths of the current length starting at each rule
+    let mut counts = vec![1usize; cfg.rules.len()];
+    for _ in 1..k {
+        counts = successors
+            .iter()
+            .map(|next| {
+                next.iter()
+                    .fold(0usize, |sum, &rule| sum.saturating_add(counts[rule]))
+            })
+            .collect();
+    }
+    counts
+        .into_iter()
+        .fold(0usize, |sum, count| sum.saturating_add(count))
+}
@@ -0,0 +1,307 @@
+//! A scannerless Earley recognizer for [`Cfg`]s.
+//!
+//! Terminals are matched as byte strings directly on the input. Since the grammars in this
+//! repository disagree on layout (`c.fan` mostly concatenates tokens, Nautilus inserts spaces),
+//! the recognizer can ignore whitespace, in which case all whitespace is removed from both the
+//! grammar's terminals and the input before parsing. Parse trees then follow the grammar's
+//! layout when unparsed, not the input's.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::grammar::{Cfg, Derivation, NonTerminal, RuleId, Symbol};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: RuleId,
+    dot: usize,
+    origin: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Recognizer {
+    cfg: Cfg,
+    nullable: Vec<bool>,
+    /// For each nullable nonterminal, a rule deriving the empty string through nonterminals
+    /// that became nullable before it, so empty derivations can be built without cycles
+    nullable_rule: Vec<Option<RuleId>>,
+    ignore_whitespace: bool,
+}
+
+/// The Earley sets of an input
+struct Chart {
+    input: Vec<u8>,
+    sets: Vec<Vec<Item>>,
+    seen: Vec<HashSet<Item>>,
+}
+
+/// Removes all ASCII whitespace from `bytes`
+fn strip_whitespace(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect()
+}
+
+impl Recognizer {
+    pub fn new(cfg: &Cfg, ignore_whitespace: bool) -> Self {
+        let mut cfg = cfg.clone();
+        for rule in &mut cfg.rules {
+            if ignore_whitespace {
+                for symbol in &mut rule.rhs {
+                    if let Symbol::Terminal(t) = symbol {
+                        *t = strip_whitespace(t);
+                    }
+                }
+            }
+            rule.rhs
+                .retain(|symbol| !matches!(symbol, Symbol::Terminal(t) if t.is_empty()));
+        }
+
+        let mut nullable = vec![false; cfg.nonterminals.len()];
+        let mut nullable_rule = vec![None; cfg.nonterminals.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (id, rule) in cfg.rules.iter().enumerate() {
+                if !nullable[rule.lhs]
+                    && rule
+                        .rhs
+                        .iter()
+                        .all(|s| matches!(s, Symbol::NonTerminal(nt) if nullable[*nt]))
+                {
+                    nullable[rule.lhs] = true;
+                    nullable_rule[rule.lhs] = Some(id);
+                    changed = true;
+                }
+            }
+        }
+
+        Self {
+            cfg,
+            nullable,
+            nullable_rule,
+            ignore_whitespace,
+        }
+    }
+
+    /// Whether `input` is in the language of the grammar
+    pub fn recognizes(&self, input: &[u8]) -> bool {
+        let chart = self.chart(input);
+        let n = chart.input.len();
+        chart.sets[n]
+            .iter()
+            .any(|item| item.origin == 0 && self.completes(item, self.cfg.start))
+    }
+
+    /// A derivation of `input` from the start symbol, if `input` is in the language.
+    ///
+    /// For ambiguous inputs, an arbitrary derivation is returned. Its rules are those of the
+    /// grammar passed to [`Recognizer::new`].
+    pub fn parse(&self, input: &[u8]) -> Option<Derivation> {
+        let chart = self.chart(input);
+        let n = chart.input.len();
+        self.build(&chart, self.cfg.start, 0, n, &mut HashSet::new())
+    }
+
+    /// Whether `item` is a completed rule of `nt`
+    fn completes(&self, item: &Item, nt: NonTerminal) -> bool {
+        let rule = &self.cfg.rules[item.rule];
+        rule.lhs == nt && item.dot == rule.rhs.len()
+    }
+
+    fn empty_derivation(&self, nt: NonTerminal) -> Option<Derivation> {
+        let rule = self.nullable_rule[nt]?;
+        let children = self.cfg.rules[rule]
+            .rhs
+            .iter()
+            .map(|symbol| match symbol {
+                Symbol::NonTerminal(child) => self.empty_derivation(*child),
+                Symbol::Terminal(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Derivation { rule, children })
+    }
+
+    /// Builds a derivation of `nt` spanning `start..end` of the chart's input.
+    ///
+    /// `in_progress` holds the spans currently being built, to break cycles of unit rules.
+    fn build(
+        &self,
+        chart: &Chart,
+        nt: NonTerminal,
+        start: usize,
+        end: usize,
+        in_progress: &mut HashSet<(NonTerminal, usize, usize)>,
+    ) -> Option<Derivation> {
+        if start == end {
+            return self.empty_derivation(nt);
+        }
+        if !in_progress.insert((nt, start, end)) {
+            return None;
+        }
+        let derivation = chart.sets[end]
+            .iter()
+            .filter(|item| item.origin == start && self.completes(item, nt))
+            .find_map(|item| {
+                let rhs_len = self.cfg.rules[item.rule].rhs.len();
+                let children =
+                    self.build_prefix(chart, item.rule, rhs_len, start, end, in_progress)?;
+                Some(Derivation {
+                    rule: item.rule,
+                    children,
+                })
+            });
+        in_progress.remove(&(nt, start, end));
+        derivation
+    }
+
+    /// Builds the children for the first `dot` symbols of `rule` spanning `start..end`
+    fn build_prefix(
+        &self,
+        chart: &Chart,
+        rule: RuleId,
+        dot: usize,
+        start: usize,
+        end: usize,
+        in_progress: &mut HashSet<(NonTerminal, usize, usize)>,
+    ) -> Option<Vec<Derivation>> {
+        if dot == 0 {
+            return (start == end).then(Vec::new);
+        }
+        // The remaining prefix must end where the chart has the item before `dot`
+        let prefix_ends_at = |p: usize| {
+            chart.seen[p].contains(&Item {
+                rule,
+                dot: dot - 1,
+                origin: start,
+            })
+        };
+        match &self.cfg.rules[rule].rhs[dot - 1] {
+            Symbol::Terminal(t) => {
+                let p = end.checked_sub(t.len())?;
+                if p < start || chart.input[p..end] != t[..] || !prefix_ends_at(p) {
+                    return None;
+                }
+                self.build_prefix(chart, rule, dot - 1, start, p, in_progress)
+            }
+            Symbol::NonTerminal(child) => {
+                let mut origins = chart.sets[end]
+                    .iter()
+                    .filter(|item| item.origin >= start && self.completes(item, *child))
+                    .map(|item| item.origin)
+                    .collect::<Vec<_>>();
+                if self.nullable[*child] {
+                    origins.push(end);
+                }
+                origins.sort_unstable();
+                origins.dedup();
+                origins
+                    .into_iter()
+                    .filter(|&p| prefix_ends_at(p))
+                    .find_map(|p| {
+                        let derivation = self.build(chart, *child, p, end, in_progress)?;
+                        let mut children =
+                            self.build_prefix(chart, rule, dot - 1, start, p, in_progress)?;
+                        children.push(derivation);
+                        Some(children)
+                    })
+            }
+        }
+    }
+
+    fn chart(&self, input: &[u8]) -> Chart {
+        let input = if self.ignore_whitespace {
+            strip_whitespace(input)
+        } else {
+            input.to_vec()
+        };
+        let n = input.len();
+
+        let mut sets: Vec<Vec<Item>> = vec![vec![]; n + 1];
+        let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); n + 1];
+        // Items in each set that wait for a nonterminal, for completion
+        let mut waiting: Vec<HashMap<NonTerminal, Vec<Item>>> = vec![HashMap::new(); n + 1];
+
+        let add = |sets: &mut Vec<Vec<Item>>, seen: &mut Vec<HashSet<Item>>, i: usize, item| {
+            if seen[i].insert(item) {
+                sets[i].push(item);
+            }
+        };
+
+        for &rule in &self.cfg.rules_by_nt[self.cfg.start] {
+            add(
+                &mut sets,
+                &mut seen,
+                0,
+                Item {
+                    rule,
+                    dot: 0,
+                    origin: 0,
+                },
+            );
+        }
+
+        for i in 0..=n {
+            let mut next = 0;
+            while next < sets[i].len() {
+                let item = sets[i][next];
+                next += 1;
+                let rule = &self.cfg.rules[item.rule];
+                let advanced = Item {
+                    dot: item.dot + 1,
+                    ..item
+                };
+
+                match rule.rhs.get(item.dot) {
+                    None => {
+                        // Completion, origins before `i` are final by now
+                        if item.origin < i {
+                            let parents = waiting[item.origin]
+                                .get(&rule.lhs)
+                                .cloned()
+                                .unwrap_or_default();
+                            for parent in parents {
+                                add(
+                                    &mut sets,
+                                    &mut seen,
+                                    i,
+                                    Item {
+                                        dot: parent.dot + 1,
+                                        ..parent
+                                    },
+                                );
+                            }
+                        }
+                    }
+                    Some(Symbol::NonTerminal(nt)) => {
+                        waiting[i].entry(*nt).or_default().push(item);
+                        for &rule in &self.cfg.rules_by_nt[*nt] {
+                            add(
+                                &mut sets,
+                                &mut seen,
+                                i,
+                                Item {
+                                    rule,
+                                    dot: 0,
+                                    origin: i,
+                                },
+                            );
+                        }
+                        // Empty derivations complete immediately (Aycock and Horspool)
+                        if self.nullable[*nt] {
+                            add(&mut sets, &mut seen, i, advanced);
+                        }
+                    }
+                    Some(Symbol::Terminal(t)) => {
+                        if input[i..].starts_with(t) {
+                            add(&mut sets, &mut seen, i + t.len(), advanced);
+                        }
+                    }
+                }
+            }
+        }
+
+        Chart { input, sets, seen }
+    }
+}
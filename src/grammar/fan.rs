@@ -0,0 +1,301 @@
+//! Parser for the BNF subset of Fandango's `.fan` files.
+//!
+//! Supported are rules of the form `<name> ::= ...` with alternatives, groups, quoted terminals
+//! and the `*`, `+` and `?` operators. Everything else (constraints, Python code, comments) is
+//! skipped. Repetitions and groups are expanded into helper nonterminals named after the rule
+//! they occur in, so the result is a plain [`Cfg`].
+
+use std::{fs, path::Path};
+
+use libafl::Error;
+
+use crate::grammar::{Cfg, NonTerminal, Symbol};
+
+/// The nonterminal Fandango starts deriving from
+pub const START: &str = "start";
+
+#[derive(Debug, Clone)]
+enum Item {
+    Terminal(Vec<u8>),
+    NonTerminal(String),
+    Group(Vec<Vec<Item>>),
+    Repeat(Box<Item>, Repetition),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Repetition {
+    ZeroOrMore,
+    OneOrMore,
+    Optional,
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> Error {
+        Error::illegal_argument(format!("line {}: {msg}", self.line))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|&b| b == b' ' || b == b'\t' || b == b'\r')
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn name(&mut self) -> Result<String, Error> {
+        if self.peek() != Some(b'<') {
+            return Err(self.error("expected a nonterminal"));
+        }
+        let start = self.pos + 1;
+        let len = self.input[start..]
+            .iter()
+            .position(|&b| b == b'>')
+            .ok_or_else(|| self.error("unterminated nonterminal"))?;
+        self.pos = start + len + 1;
+        Ok(String::from_utf8_lossy(&self.input[start..start + len]).to_string())
+    }
+
+    fn terminal(&mut self) -> Result<Vec<u8>, Error> {
+        let quote = self.input[self.pos];
+        self.pos += 1;
+        let mut bytes = vec![];
+        loop {
+            let b = *self
+                .input
+                .get(self.pos)
+                .ok_or_else(|| self.error("unterminated terminal"))?;
+            self.pos += 1;
+            match b {
+                b'\n' => return Err(self.error("unterminated terminal")),
+                b'\\' => {
+                    let escaped = *self
+                        .input
+                        .get(self.pos)
+                        .ok_or_else(|| self.error("unterminated escape"))?;
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'0' => bytes.push(0),
+                        b'x' => {
+                            let hex = self
+                                .input
+                                .get(self.pos..self.pos + 2)
+                                .and_then(|h| core::str::from_utf8(h).ok())
+                                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                                .ok_or_else(|| self.error("invalid \\x escape"))?;
+                            self.pos += 2;
+                            bytes.push(hex);
+                        }
+                        b'\\' | b'\'' | b'"' => bytes.push(escaped),
+                        other => bytes.extend_from_slice(&[b'\\', other]),
+                    }
+                }
+                b if b == quote => return Ok(bytes),
+                b => bytes.push(b),
+            }
+        }
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Item>>, Error> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Item>, Error> {
+        let mut items = vec![];
+        loop {
+            let atom = match self.peek() {
+                Some(b'<') => Item::NonTerminal(self.name()?),
+                Some(b'\'' | b'"') => Item::Terminal(self.terminal()?),
+                Some(b'(') => {
+                    self.pos += 1;
+                    let group = self.alternatives()?;
+                    if self.peek() != Some(b')') {
+                        return Err(self.error("expected ')'"));
+                    }
+                    self.pos += 1;
+                    Item::Group(group)
+                }
+                _ => return Ok(items),
+            };
+            let item = match self.peek() {
+                Some(b'*') => Item::Repeat(Box::new(atom), Repetition::ZeroOrMore),
+                Some(b'+') => Item::Repeat(Box::new(atom), Repetition::OneOrMore),
+                Some(b'?') => Item::Repeat(Box::new(atom), Repetition::Optional),
+                _ => {
+                    items.push(atom);
+                    continue;
+                }
+            };
+            self.pos += 1;
+            items.push(item);
+        }
+    }
+}
+
+/// Lowers EBNF items into rules of `cfg`, creating helper nonterminals as needed
+struct Lowering<'a> {
+    cfg: &'a mut Cfg,
+    helpers: usize,
+}
+
+impl Lowering<'_> {
+    fn helper(&mut self, rule: &str, kind: &str) -> NonTerminal {
+        self.helpers += 1;
+        self.cfg
+            .nonterminal(&format!("{rule}_{kind}{}", self.helpers))
+    }
+
+    fn sequence(&mut self, rule: &str, items: &[Item]) -> Vec<Symbol> {
+        items.iter().map(|item| self.item(rule, item)).collect()
+    }
+
+    fn item(&mut self, rule: &str, item: &Item) -> Symbol {
+        match item {
+            Item::Terminal(t) => Symbol::Terminal(t.clone()),
+            Item::NonTerminal(name) => Symbol::NonTerminal(self.cfg.nonterminal(name)),
+            Item::Group(alternatives) => {
+                let helper = self.helper(rule, "group");
+                for alternative in alternatives {
+                    let rhs = self.sequence(rule, alternative);
+                    self.cfg.add_rule(helper, rhs);
+                }
+                Symbol::NonTerminal(helper)
+            }
+            Item::Repeat(inner, repetition) => {
+                let inner = self.item(rule, inner);
+                let (kind, alternatives) = match repetition {
+                    Repetition::Optional => ("opt", vec![vec![], vec![inner]]),
+                    Repetition::ZeroOrMore => ("star", vec![vec![], vec![inner]]),
+                    Repetition::OneOrMore => ("plus", vec![vec![inner.clone()], vec![inner]]),
+                };
+                let helper = self.helper(rule, kind);
+                for (i, mut rhs) in alternatives.into_iter().enumerate() {
+                    // The second alternative of `*` and `+` recurses into the helper
+                    if i == 1 && !matches!(repetition, Repetition::Optional) {
+                        rhs.push(Symbol::NonTerminal(helper));
+                    }
+                    self.cfg.add_rule(helper, rhs);
+                }
+                Symbol::NonTerminal(helper)
+            }
+        }
+    }
+}
+
+/// Parses the rules of a `.fan` file into a [`Cfg`] starting at `<start>`
+pub fn parse(source: &[u8]) -> Result<Cfg, Error> {
+    let mut cfg = Cfg::default();
+    let start = cfg.nonterminal(START);
+    cfg.start = start;
+    let mut lowering = Lowering {
+        cfg: &mut cfg,
+        helpers: 0,
+    };
+
+    let mut parser = Parser {
+        input: source,
+        pos: 0,
+        line: 0,
+    };
+    while parser.pos < source.len() {
+        parser.line += 1;
+        let line_end = source[parser.pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(source.len(), |p| parser.pos + p);
+        let is_rule = source[parser.pos..line_end].windows(3).any(|w| w == b"::=")
+            && parser.peek() == Some(b'<');
+        if !is_rule {
+            parser.pos = line_end + 1;
+            continue;
+        }
+
+        let name = parser.name()?;
+        parser.skip_whitespace();
+        if !parser.input[parser.pos..].starts_with(b"::=") {
+            return Err(parser.error("expected '::='"));
+        }
+        parser.pos += 3;
+        let alternatives = parser.alternatives()?;
+        if parser.peek() == Some(b';') {
+            parser.pos += 1;
+        }
+        if !matches!(parser.peek(), None | Some(b'\n' | b'#')) {
+            return Err(parser.error("unexpected input after rule"));
+        }
+        parser.pos = line_end + 1;
+
+        let lhs = lowering.cfg.nonterminal(&name);
+        for alternative in &alternatives {
+            let rhs = lowering.sequence(&name, alternative);
+            lowering.cfg.add_rule(lhs, rhs);
+        }
+    }
+    Ok(cfg)
+}
+
+pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Cfg, Error> {
+    let path = path.as_ref();
+    parse(&fs::read(path)?).map_err(|e| Error::illegal_argument(format!("{}: {e}", path.display())))
+}
+
+pub(super) fn quote(terminal: &[u8]) -> String {
+    let mut quoted = String::from("'");
+    for &b in terminal {
+        match b {
+            b'\\' => quoted.push_str("\\\\"),
+            b'\'' => quoted.push_str("\\'"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            b' '..=b'~' => quoted.push(b as char),
+            b => quoted.push_str(&format!("\\x{b:02x}")),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Writes `cfg` as `.fan` rules, one line per nonterminal
+pub fn to_file<P: AsRef<Path>>(cfg: &Cfg, path: P) -> Result<(), Error> {
+    let mut fan = String::new();
+    if cfg.nonterminals[cfg.start] != START {
+        fan.push_str(&format!(
+            "<{START}> ::= <{}>\n",
+            cfg.nonterminals[cfg.start]
+        ));
+    }
+    for (nt, rules) in cfg.rules_by_nt.iter().enumerate() {
+        if rules.is_empty() {
+            continue;
+        }
+        let alternatives = rules
+            .iter()
+            .map(|&r| cfg.display_rhs(r))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        fan.push_str(&format!("<{}> ::= {alternatives}\n", cfg.nonterminals[nt]));
+    }
+    fs::write(path, fan)?;
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use core::num::NonZeroUsize;
+
+use libafl::{generators::Generator, inputs::BytesInput, state::HasRand, Error};
+use libafl_bolts::rands::Rand;
+
+use crate::grammar::{Cfg, Derivation, NonTerminal, RuleId, Symbol};
+
+/// Random derivations of a [`Cfg`], with control over their depth and size.
+///
+/// Rules are picked among those that can still finish within `max_depth`, uniformly or
+/// proportionally to per-rule weights. Once a derivation has `max_size` nodes, only the rules
+/// with the smallest derivations are picked, so generation always terminates for productive
+/// grammars.
+#[derive(Debug, Clone)]
+pub struct CfgGenerator {
+    cfg: Cfg,
+    rule_heights: Vec<Option<usize>>,
+    max_depth: usize,
+    max_size: usize,
+    weights: Option<Vec<f64>>,
+}
+
+impl CfgGenerator {
+    pub fn new(cfg: Cfg, max_depth: usize, max_size: usize) -> Result<Self, Error> {
+        if cfg.min_heights()[cfg.start].is_none() {
+            return Err(Error::illegal_argument(format!(
+                "<{}> does not derive any string",
+                cfg.nonterminals[cfg.start]
+            )));
+        }
+        Ok(Self {
+            rule_heights: cfg.rule_min_heights(),
+            cfg,
+            max_depth,
+            max_size,
+            weights: None,
+        })
+    }
+
+    /// Picks rules proportionally to `weights`, indexed by rule
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        assert_eq!(weights.len(), self.cfg.rules.len(), "one weight per rule");
+        self.weights = Some(weights);
+        self
+    }
+
+    pub fn cfg(&self) -> &Cfg {
+        &self.cfg
+    }
+
+    pub fn weights(&self) -> Option<&[f64]> {
+        self.weights.as_deref()
+    }
+
+    pub fn weights_mut(&mut self) -> Option<&mut Vec<f64>> {
+        self.weights.as_mut()
+    }
+
+    /// Picks the rule to expand `nt` with at `depth`
+    fn choose<R: Rand>(&self, rand: &mut R, nt: NonTerminal, depth: usize, size: usize) -> RuleId {
+        let productive = self.cfg.rules_by_nt[nt]
+            .iter()
+            .filter_map(|&rule| self.rule_heights[rule].map(|height| (rule, height)));
+        let min_height = productive.clone().map(|(_, height)| height).min().unwrap();
+
+        let candidates = if size >= self.max_size {
+            productive
+                .filter(|&(_, height)| height == min_height)
+                .map(|(rule, _)| rule)
+                .collect::<Vec<_>>()
+        } else {
+            let remaining = self.max_depth.saturating_sub(depth).max(min_height);
+            productive
+                .filter(|&(_, height)| height <= remaining)
+                .map(|(rule, _)| rule)
+                .collect::<Vec<_>>()
+        };
+        let Some(weights) = &self.weights else {
+            return candidates[rand.below(NonZeroUsize::new(candidates.len()).unwrap())];
+        };
+        let total = candidates.iter().map(|&rule| weights[rule]).sum::<f64>();
+        let mut target = rand.next_float() * total;
+        for &rule in &candidates {
+            target -= weights[rule];
+            if target < 0.0 {
+                return rule;
+            }
+        }
+        // Rounding, or all candidates have weight zero
+        candidates[rand.below(NonZeroUsize::new(candidates.len()).unwrap())]
+    }
+
+    /// Generates a random derivation from the start symbol
+    pub fn derive<R: Rand>(&self, rand: &mut R) -> Derivation {
+        let mut size = 0;
+        self.derive_from(rand, self.cfg.start, 0, &mut size)
+    }
+
+    /// Generates a random derivation of `nt`, as if it occurred at `depth`
+    pub fn derive_nonterminal<R: Rand>(
+        &self,
+        rand: &mut R,
+        nt: NonTerminal,
+        depth: usize,
+    ) -> Derivation {
+        let mut size = 0;
+        self.derive_from(rand, nt, depth, &mut size)
+    }
+
+    /// The height of the smallest derivation tree of each rule, see [`Cfg::rule_min_heights`]
+    pub fn rule_heights(&self) -> &[Option<usize>] {
+        &self.rule_heights
+    }
+
+    fn derive_from<R: Rand>(
+        &self,
+        rand: &mut R,
+        nt: NonTerminal,
+        depth: usize,
+        size: &mut usize,
+    ) -> Derivation {
+        let rule = self.choose(rand, nt, depth, *size);
+        *size += 1;
+        let children = self.cfg.rules[rule]
+            .rhs
+            .iter()
+            .filter_map(|symbol| match symbol {
+                Symbol::NonTerminal(child) => Some(self.derive_from(rand, *child, depth + 1, size)),
+                Symbol::Terminal(_) => None,
+            })
+            .collect();
+        Derivation { rule, children }
+    }
+}
+
+impl<S> Generator<BytesInput, S> for CfgGenerator
+where
+    S: HasRand,
+{
+    fn generate(&mut self, state: &mut S) -> Result<BytesInput, Error> {
+        let derivation = self.derive(state.rand_mut());
+        let mut bytes = vec![];
+        self.cfg.unparse(&derivation, &mut bytes);
+        Ok(BytesInput::new(bytes))
+    }
+}
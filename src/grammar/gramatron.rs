@@ -0,0 +1,133 @@
+//! Compiles a [`Cfg`] into a Gramatron automaton.
+//!
+//! Gramatron walks a finite automaton whose transitions emit terminals. Like Gramatron's own
+//! `construct_automata.py`, this approximates the grammar's pushdown automaton by bounding the
+//! stack: a state is the stack of symbols still to derive, top first, and expanding the top
+//! nonterminal with a rule replaces it by the rule's right-hand side, emitting the terminals
+//! that end up on top. Expansions that would exceed the stack bound are dropped, so the
+//! automaton accepts a subset of the grammar's language, and states that cannot reach the
+//! empty stack anymore are removed.
+
+use std::collections::{HashMap, VecDeque};
+
+use libafl::{
+    generators::gramatron::{Automaton, Trigger},
+    Error,
+};
+
+use crate::grammar::{Cfg, Symbol};
+
+/// Builds the automaton of `cfg` with stacks of at most `max_stack` symbols, failing if it
+/// would have more than `max_states` states
+pub fn automaton(cfg: &Cfg, max_stack: usize, max_states: usize) -> Result<Automaton, Error> {
+    let mut ids: HashMap<Vec<Symbol>, usize> = HashMap::new();
+    let mut stacks = vec![];
+    let mut transitions: Vec<Vec<(usize, Vec<u8>)>> = vec![];
+
+    let init = vec![Symbol::NonTerminal(cfg.start)];
+    ids.insert(init.clone(), 0);
+    stacks.push(init);
+    transitions.push(vec![]);
+    let mut queue = VecDeque::from([0]);
+
+    while let Some(state) = queue.pop_front() {
+        let Some((Symbol::NonTerminal(nt), rest)) = stacks[state].split_first() else {
+            // Only the empty stack is final, and no stack starts with a terminal
+            continue;
+        };
+        let (nt, rest) = (*nt, rest.to_vec());
+        for &rule in &cfg.rules_by_nt[nt] {
+            let mut stack = cfg.rules[rule].rhs.clone();
+            stack.extend_from_slice(&rest);
+
+            let mut term = vec![];
+            let leading = stack
+                .iter()
+                .take_while(|symbol| matches!(symbol, Symbol::Terminal(_)))
+                .count();
+            for symbol in stack.drain(..leading) {
+                if let Symbol::Terminal(t) = symbol {
+                    term.extend_from_slice(&t);
+                }
+            }
+            if stack.len() > max_stack {
+                continue;
+            }
+
+            let dest = match ids.get(&stack) {
+                Some(&id) => id,
+                None => {
+                    let id = stacks.len();
+                    if id >= max_states {
+                        return Err(Error::illegal_argument(format!(
+                            "The Gramatron automaton would have more than {max_states} states, lower the stack bound of {max_stack}"
+                        )));
+                    }
+                    ids.insert(stack.clone(), id);
+                    stacks.push(stack);
+                    transitions.push(vec![]);
+                    queue.push_back(id);
+                    id
+                }
+            };
+            transitions[state].push((dest, term));
+        }
+    }
+
+    let final_state = ids.get(&vec![]).copied().ok_or_else(|| {
+        Error::illegal_argument(format!(
+            "No derivation of <{}> fits within a stack of {max_stack} symbols",
+            cfg.nonterminals[cfg.start]
+        ))
+    })?;
+
+    // Keep only the states from which the final state can be reached
+    let mut predecessors = vec![vec![]; stacks.len()];
+    for (state, outgoing) in transitions.iter().enumerate() {
+        for (dest, _) in outgoing {
+            predecessors[*dest].push(state);
+        }
+    }
+    let mut live = vec![false; stacks.len()];
+    live[final_state] = true;
+    let mut queue = VecDeque::from([final_state]);
+    while let Some(state) = queue.pop_front() {
+        for &pred in &predecessors[state] {
+            if !live[pred] {
+                live[pred] = true;
+                queue.push_back(pred);
+            }
+        }
+    }
+
+    let mut renumbered = vec![None; stacks.len()];
+    let mut next = 0;
+    for (state, &is_live) in live.iter().enumerate() {
+        if is_live {
+            renumbered[state] = Some(next);
+            next += 1;
+        }
+    }
+    let pda = transitions
+        .into_iter()
+        .enumerate()
+        .filter(|&(state, _)| live[state])
+        .map(|(_, outgoing)| {
+            outgoing
+                .into_iter()
+                .filter_map(|(dest, term)| {
+                    renumbered[dest].map(|dest| Trigger {
+                        dest,
+                        term: String::from_utf8_lossy(&term).into_owned(),
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(Automaton {
+        init_state: renumbered[0].unwrap(),
+        final_state: renumbered[final_state].unwrap(),
+        pda,
+    })
+}
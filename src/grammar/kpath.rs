@@ -0,0 +1,245 @@
+//! Systematic generation for k-path coverage, after Havrikov and Zeller, "Systematically
+//! Covering Input Structure" (ASE 2019).
+//!
+//! Each generated derivation is built around a k-path that no earlier derivation of the current
+//! round contained: a shortest route leads from the start symbol to the path's first rule, the
+//! path's rules are chained below it, and all remaining nonterminals are expanded randomly.
+//! Once every k-path of the grammar is covered, the next round starts from scratch.
+
+use std::collections::{HashSet, VecDeque};
+
+use libafl::{generators::Generator, inputs::BytesInput, state::HasRand, Error, HasMetadata};
+use libafl_bolts::{rands::Rand, SerdeAny};
+use serde::{Deserialize, Serialize};
+
+use crate::grammar::{
+    coverage::{count_k_paths, k_paths},
+    generator::CfgGenerator,
+    Cfg, Derivation, NonTerminal, RuleId, Symbol,
+};
+
+/// Grammars with more k-paths than this are rejected, enumerating them would take too long
+const MAX_TARGETS: usize = 1_000_000;
+
+/// The nonterminals on the right-hand side of `rule`, in order
+fn nonterminal_children(cfg: &Cfg, rule: RuleId) -> impl Iterator<Item = NonTerminal> + '_ {
+    cfg.rules[rule]
+        .rhs
+        .iter()
+        .filter_map(|symbol| match symbol {
+            Symbol::NonTerminal(nt) => Some(*nt),
+            Symbol::Terminal(_) => None,
+        })
+}
+
+/// How far a [`KPathGenerator`] got in the current round. Kept in the state while fuzzing, so
+/// a restarted client continues the round instead of starting over.
+#[derive(Debug, Default, Serialize, Deserialize, SerdeAny)]
+pub struct KPathProgress {
+    /// Targets left in this round, some may have been covered by the way
+    queue: VecDeque<usize>,
+    covered: HashSet<Vec<RuleId>>,
+    round: usize,
+}
+
+/// Generates derivations that each cover a k-path not covered before in the current round
+#[derive(Debug)]
+pub struct KPathGenerator {
+    generator: CfgGenerator,
+    k: usize,
+    /// All k-paths that can occur in derivations from the start symbol
+    targets: Vec<Vec<RuleId>>,
+    progress: KPathProgress,
+    /// For each nonterminal reachable from the start symbol (except the start symbol itself),
+    /// the rule and index among its nonterminal children through which a shortest route
+    /// reaches it
+    route: Vec<Option<(RuleId, usize)>>,
+}
+
+impl KPathGenerator {
+    pub fn new(generator: CfgGenerator, k: usize) -> Result<Self, Error> {
+        let k = k.max(1);
+        let cfg = generator.cfg();
+        let total = count_k_paths(cfg, k);
+        if total > MAX_TARGETS {
+            return Err(Error::illegal_argument(format!(
+                "The grammar has {total} {k}-paths, more than the supported {MAX_TARGETS}"
+            )));
+        }
+
+        let productive = |rule: RuleId| generator.rule_heights()[rule].is_some();
+
+        let mut route = vec![None; cfg.nonterminals.len()];
+        let mut reachable = vec![false; cfg.nonterminals.len()];
+        reachable[cfg.start] = true;
+        let mut bfs = VecDeque::from([cfg.start]);
+        while let Some(nt) = bfs.pop_front() {
+            for &rule in cfg.rules_by_nt[nt].iter().filter(|&&r| productive(r)) {
+                for (index, child) in nonterminal_children(cfg, rule).enumerate() {
+                    if !reachable[child] {
+                        reachable[child] = true;
+                        route[child] = Some((rule, index));
+                        bfs.push_back(child);
+                    }
+                }
+            }
+        }
+
+        let mut targets = vec![];
+        let mut path = vec![];
+        for rule in 0..cfg.rules.len() {
+            if reachable[cfg.rules[rule].lhs] && productive(rule) {
+                Self::extend_targets(cfg, &productive, k, rule, &mut path, &mut targets);
+            }
+        }
+
+        Ok(Self {
+            progress: KPathProgress {
+                queue: (0..targets.len()).collect(),
+                ..KPathProgress::default()
+            },
+            generator,
+            k,
+            targets,
+            route,
+        })
+    }
+
+    /// Adds all productive k-paths starting with `path` followed by `rule` to `targets`
+    fn extend_targets(
+        cfg: &Cfg,
+        productive: &impl Fn(RuleId) -> bool,
+        k: usize,
+        rule: RuleId,
+        path: &mut Vec<RuleId>,
+        targets: &mut Vec<Vec<RuleId>>,
+    ) {
+        path.push(rule);
+        if path.len() == k {
+            targets.push(path.clone());
+        } else {
+            let mut children = nonterminal_children(cfg, rule).collect::<Vec<_>>();
+            children.sort_unstable();
+            children.dedup();
+            for child in children {
+                for &next in cfg.rules_by_nt[child].iter().filter(|&&r| productive(r)) {
+                    Self::extend_targets(cfg, productive, k, next, path, targets);
+                }
+            }
+        }
+        path.pop();
+    }
+
+    pub fn cfg(&self) -> &Cfg {
+        self.generator.cfg()
+    }
+
+    /// The round of `progress` and how many k-paths are covered in it
+    pub fn summary(&self, progress: &KPathProgress) -> String {
+        format!(
+            "round {}: {}/{} {}-paths",
+            progress.round,
+            progress.covered.len(),
+            self.targets.len(),
+            self.k
+        )
+    }
+
+    /// The next k-path not covered in this round, starting a new round if all are covered
+    fn next_target(&mut self) -> Option<usize> {
+        let progress = &mut self.progress;
+        if let Some(target) = progress
+            .queue
+            .iter()
+            .position(|&t| !progress.covered.contains(&self.targets[t]))
+        {
+            progress.queue.drain(..target);
+            return progress.queue.pop_front();
+        }
+        if self.targets.is_empty() {
+            return None;
+        }
+        progress.round += 1;
+        progress.covered.clear();
+        progress.queue = (0..self.targets.len()).collect();
+        progress.queue.pop_front()
+    }
+
+    /// A derivation covering the next uncovered k-path
+    pub fn derive<R: Rand>(&mut self, rand: &mut R) -> Derivation {
+        let derivation = match self.next_target() {
+            Some(target) => self.derive_covering(rand, target),
+            None => self.generator.derive(rand),
+        };
+        self.progress.covered.extend(k_paths(&derivation, self.k));
+        derivation
+    }
+
+    fn derive_covering<R: Rand>(&self, rand: &mut R, target: usize) -> Derivation {
+        let cfg = self.generator.cfg();
+        let path = &self.targets[target];
+
+        // The route from the start symbol down to the first rule of the path, top-down
+        let mut steps = vec![];
+        let mut nt = cfg.rules[path[0]].lhs;
+        while let Some((rule, index)) = self.route[nt] {
+            steps.push((rule, index));
+            nt = cfg.rules[rule].lhs;
+        }
+        steps.reverse();
+
+        // Then the path itself, each rule continuing at the first child its successor expands
+        for window in path.windows(2) {
+            let next_lhs = cfg.rules[window[1]].lhs;
+            let index = nonterminal_children(cfg, window[0])
+                .position(|child| child == next_lhs)
+                .unwrap();
+            steps.push((window[0], index));
+        }
+        let last = *path.last().unwrap();
+        self.build(rand, &steps, last, 0)
+    }
+
+    /// Builds the derivation along `steps`, ending in `last`, expanding everything else randomly
+    fn build<R: Rand>(
+        &self,
+        rand: &mut R,
+        steps: &[(RuleId, usize)],
+        last: RuleId,
+        depth: usize,
+    ) -> Derivation {
+        let cfg = self.generator.cfg();
+        let (rule, on_path) = match steps.first() {
+            Some(&(rule, index)) => (rule, Some(index)),
+            None => (last, None),
+        };
+        let children = nonterminal_children(cfg, rule)
+            .enumerate()
+            .map(|(index, child)| {
+                if Some(index) == on_path {
+                    self.build(rand, &steps[1..], last, depth + 1)
+                } else {
+                    self.generator.derive_nonterminal(rand, child, depth + 1)
+                }
+            })
+            .collect();
+        Derivation { rule, children }
+    }
+}
+
+impl<S> Generator<BytesInput, S> for KPathGenerator
+where
+    S: HasRand + HasMetadata,
+{
+    fn generate(&mut self, state: &mut S) -> Result<BytesInput, Error> {
+        // Continue with the progress of the state, if any, and leave the new one there
+        if let Some(progress) = state.metadata_map_mut().remove::<KPathProgress>() {
+            self.progress = *progress;
+        }
+        let derivation = self.derive(state.rand_mut());
+        state.add_metadata(core::mem::take(&mut self.progress));
+        let mut bytes = vec![];
+        self.cfg().unparse(&derivation, &mut bytes);
+        Ok(BytesInput::new(bytes))
+    }
+}
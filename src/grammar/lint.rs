@@ -0,0 +1,108 @@
+//! Static checks for grammars.
+//!
+//! Broken grammars otherwise surface late: `NautilusContext` panics on undefined nonterminals
+//! and Fandango silently fails to produce inputs for rules that never terminate.
+
+use core::fmt;
+
+use crate::grammar::{Cfg, Symbol};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Harmless for generation, but likely a mistake in the grammar
+    Warning,
+    /// Generation fails or never terminates
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// Which nonterminals can be reached from the start symbol
+fn reachable(cfg: &Cfg) -> Vec<bool> {
+    let mut reachable = vec![false; cfg.nonterminals.len()];
+    reachable[cfg.start] = true;
+    let mut stack = vec![cfg.start];
+    while let Some(nt) = stack.pop() {
+        for &rule in &cfg.rules_by_nt[nt] {
+            for symbol in &cfg.rules[rule].rhs {
+                if let Symbol::NonTerminal(child) = symbol {
+                    if !reachable[*child] {
+                        reachable[*child] = true;
+                        stack.push(*child);
+                    }
+                }
+            }
+        }
+    }
+    reachable
+}
+
+/// Checks `cfg` for undefined, unreachable and unproductive nonterminals, and for nonterminals
+/// whose smallest derivation is deeper than `max_depth`.
+///
+/// Problems with nonterminals that are reachable from the start symbol are errors, the others
+/// are warnings.
+pub fn lint(cfg: &Cfg, max_depth: usize) -> Vec<Finding> {
+    let reachable = reachable(cfg);
+    let heights = cfg.min_heights();
+    let severity = |nt: usize| {
+        if reachable[nt] {
+            Severity::Error
+        } else {
+            Severity::Warning
+        }
+    };
+
+    let mut findings = vec![];
+    for (nt, name) in cfg.nonterminals.iter().enumerate() {
+        if cfg.rules_by_nt[nt].is_empty() {
+            let users = cfg
+                .rules
+                .iter()
+                .filter(|rule| rule.rhs.contains(&Symbol::NonTerminal(nt)))
+                .map(|rule| format!("<{}>", cfg.nonterminals[rule.lhs]))
+                .collect::<Vec<_>>();
+            findings.push(Finding {
+                severity: severity(nt),
+                message: format!("<{name}> is undefined, used by {}", users.join(", ")),
+            });
+            continue;
+        }
+        if !reachable[nt] {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: format!(
+                    "<{name}> is unreachable from <{}>",
+                    cfg.nonterminals[cfg.start]
+                ),
+            });
+        }
+        match heights[nt] {
+            None => findings.push(Finding {
+                severity: severity(nt),
+                message: format!("<{name}> is unproductive, it never derives a string"),
+            }),
+            Some(height) if height > max_depth => findings.push(Finding {
+                severity: severity(nt),
+                message: format!(
+                    "<{name}> needs a derivation of depth {height}, more than the limit of {max_depth}"
+                ),
+            }),
+            Some(_) => {}
+        }
+    }
+    findings
+}
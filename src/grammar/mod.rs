@@ -0,0 +1,185 @@
+pub mod compare;
+pub mod coverage;
+pub mod earley;
+pub mod fan;
+pub mod generator;
+pub mod gramatron;
+pub mod kpath;
+pub mod lint;
+pub mod nautilus;
+pub mod weights;
+
+use std::{collections::HashMap, path::Path};
+
+use libafl::Error;
+
+pub type NonTerminal = usize;
+pub type RuleId = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Terminal(Vec<u8>),
+    NonTerminal(NonTerminal),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub lhs: NonTerminal,
+    pub rhs: Vec<Symbol>,
+}
+
+/// A plain context-free grammar, the common representation of `.fan` and Nautilus grammars.
+///
+/// Rules are identified by their index, which stays stable as long as the grammar is not
+/// modified, so it can be used to key per-rule statistics.
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    pub nonterminals: Vec<String>,
+    pub rules: Vec<Rule>,
+    pub rules_by_nt: Vec<Vec<RuleId>>,
+    pub start: NonTerminal,
+    ids: HashMap<String, NonTerminal>,
+}
+
+impl Cfg {
+    /// Returns the id of the nonterminal `name`, creating it if necessary
+    pub fn nonterminal(&mut self, name: &str) -> NonTerminal {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.nonterminals.len();
+        self.nonterminals.push(name.to_string());
+        self.rules_by_nt.push(vec![]);
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub fn nonterminal_id(&self, name: &str) -> Option<NonTerminal> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn add_rule(&mut self, lhs: NonTerminal, rhs: Vec<Symbol>) -> RuleId {
+        let id = self.rules.len();
+        self.rules.push(Rule { lhs, rhs });
+        self.rules_by_nt[lhs].push(id);
+        id
+    }
+
+    /// The right-hand side of `rule` in `.fan` notation
+    pub fn display_rhs(&self, rule: RuleId) -> String {
+        let rhs = &self.rules[rule].rhs;
+        if rhs.is_empty() {
+            return "''".to_string();
+        }
+        rhs.iter()
+            .map(|symbol| match symbol {
+                Symbol::Terminal(t) => fan::quote(t),
+                Symbol::NonTerminal(nt) => format!("<{}>", self.nonterminals[*nt]),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// `rule` in `.fan` notation
+    pub fn display_rule(&self, rule: RuleId) -> String {
+        format!(
+            "<{}> ::= {}",
+            self.nonterminals[self.rules[rule].lhs],
+            self.display_rhs(rule)
+        )
+    }
+
+    /// The height of the smallest derivation tree of each rule, `None` if the rule can never
+    /// derive a string of terminals.
+    pub fn rule_min_heights(&self) -> Vec<Option<usize>> {
+        let mut nt_heights: Vec<Option<usize>> = vec![None; self.nonterminals.len()];
+        let mut rule_heights = vec![None; self.rules.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (id, rule) in self.rules.iter().enumerate() {
+                let height = rule
+                    .rhs
+                    .iter()
+                    .try_fold(1, |height: usize, symbol| match symbol {
+                        Symbol::Terminal(_) => Some(height),
+                        Symbol::NonTerminal(nt) => nt_heights[*nt].map(|h| height.max(h + 1)),
+                    });
+                if height.is_some() && (rule_heights[id].is_none() || height < rule_heights[id]) {
+                    rule_heights[id] = height;
+                    if nt_heights[rule.lhs].is_none() || height < nt_heights[rule.lhs] {
+                        nt_heights[rule.lhs] = height;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        rule_heights
+    }
+
+    /// The height of the smallest derivation tree of each nonterminal, `None` if unproductive
+    pub fn min_heights(&self) -> Vec<Option<usize>> {
+        let rule_heights = self.rule_min_heights();
+        self.rules_by_nt
+            .iter()
+            .map(|rules| rules.iter().filter_map(|&r| rule_heights[r]).min())
+            .collect()
+    }
+
+    /// Appends the string derived by `derivation` to `out`
+    pub fn unparse(&self, derivation: &Derivation, out: &mut Vec<u8>) {
+        let mut children = derivation.children.iter();
+        for symbol in &self.rules[derivation.rule].rhs {
+            match symbol {
+                Symbol::Terminal(t) => out.extend_from_slice(t),
+                Symbol::NonTerminal(_) => self.unparse(children.next().unwrap(), out),
+            }
+        }
+    }
+}
+
+/// A derivation tree, with one child per nonterminal on the right-hand side of `rule`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Derivation {
+    pub rule: RuleId,
+    pub children: Vec<Derivation>,
+}
+
+impl Derivation {
+    /// The rules of the derivation in pre-order
+    pub fn rules(&self) -> Vec<RuleId> {
+        let mut rules = vec![];
+        let mut stack = vec![self];
+        while let Some(derivation) = stack.pop() {
+            rules.push(derivation.rule);
+            stack.extend(derivation.children.iter().rev());
+        }
+        rules
+    }
+}
+
+/// Loads a `.fan` or Nautilus `.json` grammar, depending on the extension of `path`
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Cfg, Error> {
+    let path = path.as_ref();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("fan") => fan::from_file(path),
+        Some("json") => nautilus::from_file(path),
+        _ => Err(Error::illegal_argument(format!(
+            "Unknown grammar format: {}",
+            path.display()
+        ))),
+    }
+}
+
+/// Writes a `.fan` or Nautilus `.json` grammar, depending on the extension of `path`
+pub fn store<P: AsRef<Path>>(cfg: &Cfg, path: P) -> Result<(), Error> {
+    let path = path.as_ref();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("fan") => fan::to_file(cfg, path),
+        Some("json") => nautilus::to_file(cfg, path),
+        _ => Err(Error::illegal_argument(format!(
+            "Unknown grammar format: {}",
+            path.display()
+        ))),
+    }
+}
@@ -0,0 +1,128 @@
+//! Reading and writing Nautilus' JSON grammars, i.e. lists of `[NONTERMINAL, "text {CHILD}"]`.
+
+use std::{collections::HashSet, fs, path::Path};
+
+use libafl::Error;
+use serde::Serialize as _;
+
+use crate::grammar::{Cfg, Symbol};
+
+/// Parses Nautilus rules into a [`Cfg`].
+///
+/// Like `NautilusContext`, the nonterminal of the first rule is the start symbol.
+pub fn parse(rules: &[(String, String)]) -> Result<Cfg, Error> {
+    let mut cfg = Cfg::default();
+    for (lhs, format) in rules {
+        let lhs = cfg.nonterminal(lhs);
+        let mut rhs = vec![];
+        let mut literal = vec![];
+        let mut chars = format.bytes().peekable();
+        while let Some(b) = chars.next() {
+            match b {
+                b'\\' if matches!(chars.peek(), Some(b'{' | b'}')) => {
+                    literal.push(chars.next().unwrap());
+                }
+                b'{' => {
+                    let name = chars
+                        .by_ref()
+                        .take_while(|&b| b != b'}')
+                        .collect::<Vec<_>>();
+                    if !literal.is_empty() {
+                        rhs.push(Symbol::Terminal(core::mem::take(&mut literal)));
+                    }
+                    let name = String::from_utf8_lossy(&name);
+                    rhs.push(Symbol::NonTerminal(cfg.nonterminal(&name)));
+                }
+                b => literal.push(b),
+            }
+        }
+        if !literal.is_empty() {
+            rhs.push(Symbol::Terminal(literal));
+        }
+        cfg.add_rule(lhs, rhs);
+    }
+    if cfg.rules.is_empty() {
+        return Err(Error::illegal_argument("Nautilus grammar without rules"));
+    }
+    cfg.start = cfg.rules[0].lhs;
+    Ok(cfg)
+}
+
+pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Cfg, Error> {
+    let rules: Vec<(String, String)> = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| Error::serialize(format!("Invalid Nautilus grammar: {e}")))?;
+    parse(&rules)
+}
+
+/// Nautilus nonterminal names for all nonterminals of `cfg`, upper-cased like in `c.json`
+fn nautilus_names(cfg: &Cfg) -> Vec<String> {
+    let mut taken = HashSet::new();
+    cfg.nonterminals
+        .iter()
+        .map(|name| {
+            let base = name
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() {
+                        c.to_ascii_uppercase()
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>();
+            let base = if base.starts_with(|c: char| c.is_ascii_uppercase()) {
+                base
+            } else {
+                format!("N{base}")
+            };
+            let mut candidate = base.clone();
+            let mut suffix = 1;
+            while !taken.insert(candidate.clone()) {
+                suffix += 1;
+                candidate = format!("{base}_{suffix}");
+            }
+            candidate
+        })
+        .collect()
+}
+
+/// Turns `cfg` into Nautilus rules, starting with the rules of the start symbol
+pub fn to_rules(cfg: &Cfg) -> Vec<(String, String)> {
+    let names = nautilus_names(cfg);
+    let start_rules = cfg.rules_by_nt[cfg.start].iter().copied();
+    let other_rules = (0..cfg.rules.len()).filter(|&r| cfg.rules[r].lhs != cfg.start);
+    start_rules
+        .chain(other_rules)
+        .map(|r| {
+            let rule = &cfg.rules[r];
+            let mut format = String::new();
+            for symbol in &rule.rhs {
+                match symbol {
+                    Symbol::Terminal(t) => {
+                        let t = String::from_utf8_lossy(t);
+                        format.push_str(&t.replace('{', "\\{").replace('}', "\\}"));
+                    }
+                    Symbol::NonTerminal(nt) => {
+                        format.push('{');
+                        format.push_str(&names[*nt]);
+                        format.push('}');
+                    }
+                }
+            }
+            (names[rule.lhs].clone(), format)
+        })
+        .collect()
+}
+
+pub fn to_file<P: AsRef<Path>>(cfg: &Cfg, path: P) -> Result<(), Error> {
+    // Same layout as `c.json`
+    let mut json = vec![];
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+    to_rules(cfg)
+        .serialize(&mut serializer)
+        .map_err(|e| Error::serialize(e.to_string()))?;
+    json.push(b'\n');
+    fs::write(path, json)?;
+    Ok(())
+}
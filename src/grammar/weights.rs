@@ -0,0 +1,112 @@
+//! Rule weights for [`CfgGenerator`](crate::grammar::generator::CfgGenerator), learned from how
+//! often the derivations of a corpus use each rule.
+//!
+//! Weights are stored as JSON next to the grammar, keyed by the rendered rule rather than its
+//! index, so they survive reordering the grammar.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use libafl::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::grammar::{earley::Recognizer, Cfg};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum WeightMode {
+    /// Favor the rules the corpus uses most
+    Frequent,
+    /// Favor the rules the corpus uses least
+    Inverse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WeightedRule {
+    rule: String,
+    count: usize,
+    probability: f64,
+}
+
+/// How often each rule of `cfg` occurs in the derivations of `inputs`, and how many inputs were
+/// in the language at all
+pub fn count_rules(cfg: &Cfg, inputs: &[Vec<u8>]) -> (Vec<usize>, usize) {
+    let recognizer = Recognizer::new(cfg, true);
+    let mut counts = vec![0; cfg.rules.len()];
+    let mut parsed = 0;
+    for input in inputs {
+        if let Some(derivation) = recognizer.parse(input) {
+            parsed += 1;
+            for rule in derivation.rules() {
+                counts[rule] += 1;
+            }
+        }
+    }
+    (counts, parsed)
+}
+
+/// Probabilities of picking each rule among the rules of its nonterminal.
+///
+/// Counts are smoothed by one, so rules the corpus never uses stay possible.
+pub fn probabilities(cfg: &Cfg, counts: &[usize], mode: WeightMode) -> Vec<f64> {
+    let weights = counts
+        .iter()
+        .map(|&count| match mode {
+            WeightMode::Frequent => count as f64 + 1.0,
+            WeightMode::Inverse => 1.0 / (count as f64 + 1.0),
+        })
+        .collect::<Vec<_>>();
+    normalize(cfg, &weights)
+}
+
+/// Scales `weights` so the rules of each nonterminal sum up to one
+pub fn normalize(cfg: &Cfg, weights: &[f64]) -> Vec<f64> {
+    let mut normalized = weights.to_vec();
+    for rules in &cfg.rules_by_nt {
+        let total = rules.iter().map(|&r| weights[r]).sum::<f64>();
+        for &r in rules {
+            normalized[r] /= total;
+        }
+    }
+    normalized
+}
+
+pub fn store<P: AsRef<Path>>(
+    cfg: &Cfg,
+    counts: &[usize],
+    probabilities: &[f64],
+    path: P,
+) -> Result<(), Error> {
+    let rules = (0..cfg.rules.len())
+        .map(|r| WeightedRule {
+            rule: cfg.display_rule(r),
+            count: counts[r],
+            probability: probabilities[r],
+        })
+        .collect::<Vec<_>>();
+    let json = serde_json::to_vec_pretty(&rules).map_err(|e| Error::serialize(e.to_string()))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Loads weights for the rules of `cfg`, normalized per nonterminal.
+///
+/// Rules missing from the file, e.g. `.fan` helpers numbered differently since the weights were
+/// learned, are reported and get an even share of their nonterminal, as if no weights were given.
+pub fn load<P: AsRef<Path>>(cfg: &Cfg, path: P) -> Result<Vec<f64>, Error> {
+    let rules: Vec<WeightedRule> = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| Error::serialize(format!("Invalid rule weights: {e}")))?;
+    let by_rule = rules
+        .into_iter()
+        .map(|rule| (rule.rule, rule.probability))
+        .collect::<HashMap<_, _>>();
+    let mut weights = vec![0.0; cfg.rules.len()];
+    for rules in &cfg.rules_by_nt {
+        for &r in rules {
+            let rule = cfg.display_rule(r);
+            weights[r] = by_rule.get(&rule).copied().unwrap_or_else(|| {
+                eprintln!("No weight for rule {rule}");
+                1.0 / rules.len() as f64
+            });
+        }
+    }
+    Ok(normalize(cfg, &weights))
+}
@@ -1,11 +1,19 @@
 #![feature(iter_intersperse)]
+mod cmplog;
+mod commands;
 #[macro_use]
 mod config;
+mod dictionary;
 mod executor;
 mod feedback;
+mod grammar;
+mod mutators;
 mod observer;
+#[macro_use]
+mod stages;
+mod tokenizer;
 
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory as _, Parser};
 use core::time::Duration;
 use std::{borrow::Cow, env, fs, net::SocketAddr, path::PathBuf};
 
@@ -22,11 +30,12 @@ use libafl::{
     fuzzer::{BloomInputFilter, ReportingInputFilter, StdFuzzer},
     fuzzer::{Evaluator, Fuzzer},
     monitors::{MultiMonitor, OnDiskJsonMonitor},
+    mutators::Tokens,
     observers::{
         CanTrack, HitcountsMapObserver, StdErrObserver, StdMapObserver, StdOutObserver,
         TimeObserver,
     },
-    state::StdState,
+    state::{HasMetadata as _, StdState},
     Error,
 };
 use libafl_bolts::{
@@ -34,23 +43,34 @@ use libafl_bolts::{
     current_nanos,
     rands::StdRand,
     shmem::{MmapShMemProvider, ShMem as _, ShMemProvider, StdShMemProvider},
-    tuples::tuple_list,
+    tuples::{tuple_list, Handled as _},
     AsSliceMut as _,
 };
 
 #[allow(unused_imports)]
 use {
     crate::config::seeds::{NoSeedsConfig, ValidCorpusSeedsConfig},
+    crate::mutators::{block_splice_mutations, c_literal_mutations, IdentifierConsistencyMutator},
     libafl::{
-        mutators::{havoc_mutations, havoc_mutations_no_crossover, HavocScheduledMutator},
+        mutators::{
+            havoc_mutations, havoc_mutations_no_crossover, tokens_mutations, HavocScheduledMutator,
+        },
         stages::StdMutationalStage,
     },
     libafl_bolts::tuples::Merge,
 };
 
 use crate::{
-    config::FuzzerConfig, executor::get_coverage_shmem_size, feedback::ReportCorrectnessFeedback,
-    observer::CorrectnessObserver,
+    cmplog::{ShMemCmpObserver, CMPLOG_MAP_SIZE},
+    commands::Command,
+    config::{
+        fan::FanOpt, fandango::FandangoOpt, gramatron::GramatronOpt, kpath::KPathOpt,
+        nautilus::NautilusOpt, FuzzerConfig,
+    },
+    executor::{get_coverage_shmem_size, Terminator},
+    feedback::{GrammarCoverageFeedback, ReportConformanceFeedback, ReportCorrectnessFeedback},
+    grammar::{coverage::count_k_paths, earley::Recognizer},
+    observer::{CorrectnessObserver, GrammarConformanceObserver},
 };
 
 /// Parses a millseconds int into a [`Duration`], used for commandline arg parsing
@@ -112,9 +132,40 @@ pub struct Opt {
     #[arg(long, help = "Set the stderr file", name = "STDERR_FILE")]
     stderr_file: Option<PathBuf>,
 
-    #[arg(short, long, help = "Set the grammar file", name = "GRAMMAR_FILE")]
+    #[arg(
+        short,
+        long,
+        help = "Set the grammar file prefix, .fan and .json are appended as needed, default is c",
+        name = "GRAMMAR_FILE",
+        default_value = "c"
+    )]
     grammar_file_prefix: String,
 
+    #[arg(
+        long,
+        help = "Classify executed byte-level inputs as in or out of this grammar (.fan or .json), which is slow"
+    )]
+    conformance_grammar: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Skip the conformance check for inputs longer than this many bytes",
+        default_value = "4096"
+    )]
+    conformance_max_len: usize,
+
+    #[arg(
+        long,
+        help = "Track which rules and k-paths of rules of the --conformance-grammar the executed inputs cover, parsing them instead of only recognizing"
+    )]
+    grammar_coverage_k: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Keep inputs that cover a new k-path of the --conformance-grammar, requires --grammar-coverage-k"
+    )]
+    grammar_coverage_feedback: bool,
+
     #[arg(
         short,
         long,
@@ -132,18 +183,117 @@ pub struct Opt {
         default_value = "./target/release/libsetup_guard_redirection.so"
     )]
     redirection_shared_library: PathBuf,
+
+    #[arg(
+        value_enum,
+        long,
+        help = "Set what is appended to unparsed inputs before they are passed to the target, default is nul",
+        name = "TERMINATOR",
+        default_value = "nul"
+    )]
+    terminator: Terminator,
+
+    #[arg(
+        long,
+        help = "Set the minimum number of mutations applied to each generated input, default is 0",
+        name = "POST_MUTATIONS_MIN",
+        default_value = "0"
+    )]
+    post_mutations_min: usize,
+
+    #[arg(
+        long,
+        help = "Set the maximum number of mutations applied to each generated input, default is 0",
+        name = "POST_MUTATIONS_MAX",
+        default_value = "0"
+    )]
+    post_mutations_max: usize,
+
+    #[arg(
+        long,
+        help = "Forward the operands of clang's comparisons to the fuzzer and replace them in the corpus entries with setup_cmplog_stages!, needs clang built with `just build_cmplog`"
+    )]
+    cmplog: bool,
+
+    #[arg(
+        long,
+        help = "Add clang's keywords and punctuators from this TokenKinds.def to the dictionary of the token mutations",
+        default_value = "llvm/clang/include/clang/Basic/TokenKinds.def"
+    )]
+    token_kinds: PathBuf,
+
+    #[arg(
+        long,
+        help = "Build a dictionary for the token mutations from the grammar terminals and --token-kinds"
+    )]
+    dictionary: bool,
+
+    #[command(flatten)]
+    fandango: FandangoOpt,
+
+    #[command(flatten)]
+    fan: FanOpt,
+
+    #[command(flatten)]
+    nautilus: NautilusOpt,
+
+    #[command(flatten)]
+    kpath: KPathOpt,
+
+    #[command(flatten)]
+    gramatron: GramatronOpt,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
 const NUM_GENERATED: usize = 4096;
+/// Depth limit of the Nautilus grammar context
+const NAUTILUS_MAX_DEPTH: usize = 256;
 
 #[allow(clippy::too_many_lines)]
 pub fn main() {
     let opt = Opt::parse();
+    if opt.post_mutations_min > opt.post_mutations_max {
+        Opt::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--post-mutations-min must not exceed --post-mutations-max",
+            )
+            .exit();
+    }
+
+    if let Some(command) = &opt.command {
+        if let Err(err) = commands::run(command, &opt) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Err(err) = commands::lint_before_fuzzing(&opt) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+
+    if opt.grammar_coverage_feedback
+        && (opt.grammar_coverage_k.is_none() || opt.conformance_grammar.is_none())
+    {
+        eprintln!(
+            "--grammar-coverage-feedback requires --grammar-coverage-k and --conformance-grammar"
+        );
+        std::process::exit(1);
+    }
 
     let mut initial_dir = opt.output.clone();
     initial_dir.push("initial");
     fs::create_dir_all(&initial_dir).unwrap();
 
+    if let Err(err) = opt.fandango.write_combined_grammar(&opt) {
+        eprintln!("Failed to combine the Fandango grammar and constraints: {err}");
+        std::process::exit(1);
+    }
+
     println!(
         "Workdir: {:?}",
         env::current_dir().unwrap().to_string_lossy().to_string()
@@ -167,16 +317,21 @@ pub fn main() {
         let guard_num = get_coverage_shmem_size(opt.target_binary.to_str().unwrap())?;
 
         let mut provider = MmapShMemProvider::default();
+        let cmplog_size = if opt.cmplog { CMPLOG_MAP_SIZE } else { 0 };
         let mut shmem = provider
-            .new_shmem(guard_num + size_of::<usize>())?
+            .new_shmem(size_of::<usize>() + guard_num + cmplog_size)?
             .persist()?;
         let shmem_description = shmem.description();
 
-        let (step, edges) = shmem.as_slice_mut().split_at_mut(size_of::<usize>());
+        let (step, rest) = shmem.as_slice_mut().split_at_mut(size_of::<usize>());
+        let (edges, cmps) = rest.split_at_mut(guard_num);
 
-        let edges_observer =
+        let edges_observer = CurrentConfig::edges_observer(
             HitcountsMapObserver::new(unsafe { StdMapObserver::new("edges", edges) })
-                .track_indices();
+                .track_indices(),
+        );
+        #[allow(unused_variables)]
+        let edges_handle = edges_observer.handle();
 
         // Create an observation channel to keep track of the execution time
         let time_observer = TimeObserver::new("time");
@@ -184,9 +339,27 @@ pub fn main() {
         // Custom correctness observer backed by a global no_mangle symbol
         let correctness_observer =
             CorrectnessObserver::new(step, format!("correctness_{}", core_id.core_id().0));
+        let correctness_handle = correctness_observer.handle();
+
+        let conformance_grammar = match &opt.conformance_grammar {
+            Some(path) => Some(grammar::load(path)?),
+            None => None,
+        };
+        let recognizer = conformance_grammar
+            .as_ref()
+            .map(|cfg| Recognizer::new(cfg, true));
+        let conformance_observer =
+            GrammarConformanceObserver::new(recognizer, opt.conformance_max_len)
+                .with_coverage(opt.grammar_coverage_k);
+        let k = opt.grammar_coverage_k.unwrap_or(1).max(1);
+        let (total_rules, total_paths) = conformance_grammar
+            .as_ref()
+            .map_or((0, 0), |cfg| (cfg.rules.len(), count_k_paths(cfg, k)));
 
         let stdout_observer = StdOutObserver::new(Cow::Borrowed("stdout")).unwrap();
         let stderr_observer = StdErrObserver::new(Cow::Borrowed("stderr")).unwrap();
+        #[allow(unused_variables)]
+        let stderr_handle = stderr_observer.handle();
 
         let stdout_feedback = StdOutToMetadataFeedback::new(&stdout_observer);
         let stderr_feedback = StdErrToMetadataFeedback::new(&stderr_observer);
@@ -197,6 +370,14 @@ pub fn main() {
             stdout_feedback.clone(),
             stderr_feedback.clone(),
             ReportCorrectnessFeedback::new(&correctness_observer),
+            ReportConformanceFeedback::new(&correctness_observer, &conformance_observer),
+            CurrentConfig::grammar_coverage(GrammarCoverageFeedback::new(
+                &conformance_observer,
+                k,
+                total_rules,
+                total_paths,
+                opt.grammar_coverage_feedback
+            )),
             // New maximization map feedback linked to the edges observer and the feedback state
             MaxMapFeedback::new(&edges_observer),
             // Time feedback, this one does not need a feedback state
@@ -227,6 +408,15 @@ pub fn main() {
             .unwrap()
         });
 
+        // Keywords and punctuators for the token mutations, restored along with the state
+        if opt.dictionary && !state.has_metadata::<Tokens>() {
+            let grammars = ["fan", "json"]
+                .map(|ext| PathBuf::from(format!("{}.{ext}", opt.grammar_file_prefix)));
+            let tokens = dictionary::build(&grammars, &opt.token_kinds);
+            println!("Loaded {} tokens into the dictionary", tokens.len());
+            state.add_metadata(tokens);
+        }
+
         // A minimization+queue policy to get testcasess from the corpus
         let scheduler = CurrentConfig::scheduler(&edges_observer);
 
@@ -238,14 +428,30 @@ pub fn main() {
             .objective(objective)
             .build();
 
+        // Without --cmplog, the observer and setup_cmplog_stages! do nothing
+        let cmplog_observer = if opt.cmplog {
+            ShMemCmpObserver::new(cmps)?
+        } else {
+            ShMemCmpObserver::disabled()
+        };
+        let cmplog_handle = cmplog_observer.handle();
+
+        let observers = tuple_list!(
+            edges_observer,
+            time_observer,
+            correctness_observer,
+            conformance_observer,
+            cmplog_observer
+        );
+
         let mut executor = CurrentConfig::get_executor(
             &mut init,
             stdout_observer,
             stderr_observer,
-            tuple_list!(edges_observer, time_observer, correctness_observer),
+            observers,
+            correctness_handle.clone(),
             shmem_description,
-            opt.redirection_shared_library.to_str().unwrap(),
-            opt.target_binary.to_str().unwrap(),
+            &opt,
         )?;
 
         // In case the corpus is empty (on first run), reset
@@ -265,13 +471,75 @@ pub fn main() {
 
         // type CurrentConfig = config::NautilusConfig<ValidCorpusSeedsConfig>;
         // let mut stages = setup_nautilus_stages!(&opt);
+        // type CurrentConfig = config::NautilusTreeConfig<ValidCorpusSeedsConfig>;
+        // The mutators borrow the context for the whole run
+        // let context = opt.nautilus.or_start(config::nautilus::PROGRAM_START).context(&opt)?;
+        // let mut stages = setup_nautilus_tree_stages!(&opt, &context);
+        // type CurrentConfig = config::GramatronConfig<NoSeedsConfig>;
+        // The mutators borrow the generator, and the generator the automaton, for the whole run
+        // let automaton = opt.gramatron.automaton(&opt)?;
+        // let generator = libafl::generators::GramatronGenerator::new(&automaton);
+        // let mut stages = setup_gramatron_stages!(&generator);
+        // type CurrentConfig = config::FandangoConfig<NoSeedsConfig>;
+        // let havoc = HavocScheduledMutator::new(havoc_mutations_no_crossover());
+        // let mut stages = setup_nautilus_posthavoc_stages!(&opt, havoc);
+        // let mut stages =
+        //     setup_nautilus_adaptive_stages!(&opt, havoc, correctness_handle, core_id);
+        // let mut stages = setup_nautilus_blame_stages!(
+        //     &opt,
+        //     havoc,
+        //     correctness_handle,
+        //     stderr_handle,
+        //     core_id
+        // );
+        // type CurrentConfig = config::GrimoireConfig<ValidCorpusSeedsConfig>;
+        // let mut stages = setup_grimoire_stages!(&executor, edges_handle);
+        // type CurrentConfig = config::FanConfig<NoSeedsConfig>;
+        // let havoc = HavocScheduledMutator::new(havoc_mutations_no_crossover());
+        // let mut stages = setup_fan_stages!(&opt, havoc);
+        // type CurrentConfig = config::KPathConfig<NoSeedsConfig>;
+        // let havoc = HavocScheduledMutator::new(havoc_mutations_no_crossover());
+        // let mut stages = setup_kpath_stages!(&opt, havoc);
+        #[cfg(feature = "fandango")]
         type CurrentConfig = config::FandangoConfig<NoSeedsConfig>;
-        let havoc = HavocScheduledMutator::new(havoc_mutations_no_crossover());
-        let mut stages = setup_fandango_stages!(&opt, havoc, 0, 0);
+        // Without the fandango feature, generate from c.fan with the Rust generator instead
+        #[cfg(not(feature = "fandango"))]
+        type CurrentConfig = config::FanConfig<NoSeedsConfig>;
+        // The token mutations only act with --dictionary, which puts the Tokens into the state
+        let havoc =
+            HavocScheduledMutator::new(havoc_mutations_no_crossover().merge(tokens_mutations()));
+        // let havoc = HavocScheduledMutator::new(
+        //     havoc_mutations_no_crossover()
+        //         .merge(tokens_mutations())
+        //         .merge(c_literal_mutations()),
+        // );
+        // Fixes the identifiers of every generated input with --post-mutations-min/max 1
+        // let havoc = IdentifierConsistencyMutator::new();
+        #[cfg(feature = "fandango")]
+        let mut stages = setup_fandango_stages!(&opt, havoc);
+        #[cfg(not(feature = "fandango"))]
+        let mut stages = setup_fan_stages!(&opt, havoc);
+        // let mut stages = setup_fandango_pool_stages!(&opt, havoc);
+        // let mut stages = setup_fandango_blame_stages!(
+        //     &opt,
+        //     havoc,
+        //     correctness_handle,
+        //     stderr_handle,
+        //     core_id
+        // );
+        // Crossover at bracket and statement boundaries instead of byte offsets
+        // let mut stages = tuple_list!(StdMutationalStage::new(HavocScheduledMutator::new(
+        //     havoc_mutations_no_crossover().merge(block_splice_mutations())
+        // )),)
+        // .merge(stages);
         let mut stages = tuple_list!(StdMutationalStage::new(HavocScheduledMutator::new(
-            havoc_mutations()
+            havoc_mutations().merge(tokens_mutations())
         )),)
         .merge(stages);
+        let mut stages = stages.merge(setup_cmplog_stages!(cmplog_handle));
+        // let mut stages = stages.merge(setup_valid_transform_stages!());
+        // let mut stages = stages.merge(setup_fixit_stages!(&opt, correctness_handle));
+        // let mut stages = stages.merge(setup_diagnostic_focus_stages!());
         println!("Let's fuzz!");
         fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut restarting_mgr)?;
         restarting_mgr.on_restart(&mut state)
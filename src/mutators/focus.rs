@@ -0,0 +1,195 @@
+//! Token mutations confined to a window around the first error clang reports.
+//!
+//! Uniform mutations rarely touch the few bytes that made clang give up, so these only delete,
+//! replace or insert tokens near the location stored in [`DiagnosticFocusMetadata`] by the
+//! [`DiagnosticFocusStage`](crate::stages::DiagnosticFocusStage) that runs them.
+
+use std::{borrow::Cow, num::NonZeroUsize, ops::Range};
+
+use libafl::{
+    inputs::{BytesInput, HasMutatorBytes},
+    mutators::{MutationResult, Mutator, Tokens},
+    state::{HasMaxSize, HasRand},
+    Error, HasMetadata,
+};
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+    Named, SerdeAny,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::tokenizer::{lex, Token, TokenKind};
+
+/// Tokens before the error location that are part of the window, clang often reports a
+/// missing or extra token at the one following it
+const TOKENS_BEFORE: usize = 4;
+/// Tokens from the one at the error location on that are part of the window
+const TOKENS_AFTER: usize = 2;
+
+/// The bytes of the current input the focused mutations are confined to
+#[derive(Debug, Clone, Serialize, Deserialize, SerdeAny)]
+pub struct DiagnosticFocusMetadata {
+    pub window: Range<usize>,
+}
+
+/// The window around the error at byte `offset` of `input`, from a few tokens before it to a
+/// few tokens after it
+pub fn focus_window(input: &[u8], offset: usize) -> Range<usize> {
+    let tokens = lex(input);
+    let at = tokens.partition_point(|t| t.end <= offset);
+    let first = at.saturating_sub(TOKENS_BEFORE);
+    let last = (at + TOKENS_AFTER).min(tokens.len());
+    match (tokens.get(first), tokens[..last].last()) {
+        (Some(first), Some(last)) if first.start < last.end => first.start..last.end,
+        // An input without tokens
+        _ => offset.min(input.len())..offset.min(input.len()),
+    }
+}
+
+fn pick<'a, T, R: Rand>(rand: &mut R, from: &'a [T]) -> Option<&'a T> {
+    NonZeroUsize::new(from.len()).map(|len| &from[rand.below(len)])
+}
+
+/// The tokens of `input` overlapping the focus window, and the window itself
+fn focused_tokens<S: HasMetadata>(state: &S, input: &[u8]) -> Option<(Vec<Token>, Range<usize>)> {
+    let window = state
+        .metadata::<DiagnosticFocusMetadata>()
+        .ok()?
+        .window
+        .clone();
+    // Earlier mutations of the same stack may have shortened the input
+    let window = window.start.min(input.len())..window.end.min(input.len());
+    let tokens = lex(input)
+        .into_iter()
+        .filter(|t| t.start < window.end && window.start < t.end)
+        .collect();
+    Some((tokens, window))
+}
+
+/// A random token of the dictionary built at startup with `--dictionary`, or else of `input`
+/// itself, so the mutations work without a dictionary too
+fn replacement_token<S: HasMetadata + HasRand>(state: &mut S, input: &[u8]) -> Option<Vec<u8>> {
+    if let Some(len) = state
+        .metadata::<Tokens>()
+        .ok()
+        .and_then(|tokens| NonZeroUsize::new(tokens.len()))
+    {
+        let index = state.rand_mut().below(len);
+        return Some(state.metadata::<Tokens>().ok()?.tokens()[index].clone());
+    }
+    let tokens = lex(input)
+        .into_iter()
+        .filter(|t| {
+            !matches!(
+                t.kind,
+                TokenKind::Comment | TokenKind::Directive | TokenKind::Other
+            )
+        })
+        .collect::<Vec<_>>();
+    pick(state.rand_mut(), &tokens).map(|t| input[t.start..t.end].to_vec())
+}
+
+/// What a [`DiagnosticFocusMutator`] does within the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusedMutation {
+    /// Deletes a token
+    Delete,
+    /// Replaces a token with one from the dictionary, or from the input without `--dictionary`
+    Replace,
+    /// Inserts a token like [`Self::Replace`] picks one, before or after a token, or at an
+    /// empty window
+    Insert,
+}
+
+/// Deletes, replaces or inserts a token near the first error clang reported for the input
+#[derive(Debug, Clone)]
+pub struct DiagnosticFocusMutator {
+    mutation: FocusedMutation,
+    name: Cow<'static, str>,
+}
+
+impl DiagnosticFocusMutator {
+    pub fn new(mutation: FocusedMutation) -> Self {
+        Self {
+            mutation,
+            name: Cow::Owned(format!("DiagnosticFocusMutator<{mutation:?}>")),
+        }
+    }
+}
+
+impl Named for DiagnosticFocusMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<BytesInput, S> for DiagnosticFocusMutator
+where
+    S: HasMetadata + HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut BytesInput) -> Result<MutationResult, Error> {
+        let bytes = input.mutator_bytes();
+        let Some((tokens, window)) = focused_tokens(state, bytes) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let target = pick(state.rand_mut(), &tokens).copied();
+
+        let (range, replacement) = match (self.mutation, target) {
+            (FocusedMutation::Delete, Some(token)) => (token.start..token.end, vec![]),
+            (FocusedMutation::Replace, Some(token)) => {
+                let Some(replacement) = replacement_token(state, bytes) else {
+                    return Ok(MutationResult::Skipped);
+                };
+                (token.start..token.end, replacement)
+            }
+            (FocusedMutation::Insert, target) => {
+                let Some(token) = replacement_token(state, bytes) else {
+                    return Ok(MutationResult::Skipped);
+                };
+                let at = match target {
+                    Some(target) if state.rand_mut().coinflip(0.5) => target.start,
+                    Some(target) => target.end,
+                    None => window.start,
+                };
+                (at..at, [b" ", &token[..], b" "].concat())
+            }
+            (_, None) => return Ok(MutationResult::Skipped),
+        };
+        if range.len() == replacement.len() && bytes[range.clone()] == replacement[..] {
+            return Ok(MutationResult::Skipped);
+        }
+        if bytes.len() - range.len() + replacement.len() > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let mut mutated = bytes.to_vec();
+        mutated.splice(range, replacement);
+        *input = BytesInput::new(mutated);
+        Ok(MutationResult::Mutated)
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _new_corpus_id: Option<libafl::corpus::CorpusId>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The tuple of [`diagnostic_focus_mutations`]
+pub type DiagnosticFocusMutationsType = tuple_list_type!(
+    DiagnosticFocusMutator,
+    DiagnosticFocusMutator,
+    DiagnosticFocusMutator,
+);
+
+/// One [`DiagnosticFocusMutator`] per [`FocusedMutation`]
+pub fn diagnostic_focus_mutations() -> DiagnosticFocusMutationsType {
+    tuple_list!(
+        DiagnosticFocusMutator::new(FocusedMutation::Delete),
+        DiagnosticFocusMutator::new(FocusedMutation::Replace),
+        DiagnosticFocusMutator::new(FocusedMutation::Insert),
+    )
+}
@@ -0,0 +1,837 @@
+//! Rewrites identifier uses so that they refer to declarations in scope.
+//!
+//! Grammar-based generators pick identifiers independently of each other, so most of their
+//! outputs fail in clang's semantic analysis with undeclared identifiers. Without a parser, the
+//! scopes and declarations are approximated from the tokens: a declaration starts at the
+//! beginning of a statement with a type keyword or a known type name, and ends at its `;`.
+
+use std::{borrow::Cow, collections::HashMap, num::NonZeroUsize};
+
+use libafl::{
+    inputs::{BytesInput, HasMutatorBytes},
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use libafl_bolts::{rands::Rand, Named};
+
+use crate::tokenizer::{lex, Token, TokenKind};
+
+/// Keywords and builtins that start or continue the specifiers of a declaration
+pub const SPECIFIERS: &[&[u8]] = &[
+    b"void",
+    b"char",
+    b"short",
+    b"int",
+    b"long",
+    b"float",
+    b"double",
+    b"signed",
+    b"unsigned",
+    b"_Bool",
+    b"bool",
+    b"_Complex",
+    b"_Imaginary",
+    b"_Decimal32",
+    b"_Decimal64",
+    b"_Decimal128",
+    b"const",
+    b"volatile",
+    b"restrict",
+    b"_Atomic",
+    b"static",
+    b"extern",
+    b"auto",
+    b"register",
+    b"inline",
+    b"_Thread_local",
+    b"thread_local",
+    b"_Noreturn",
+    b"typedef",
+    b"constexpr",
+    b"struct",
+    b"union",
+    b"enum",
+    b"__int128",
+    b"__builtin_va_list",
+    b"__auto_type",
+    b"__const",
+    b"__signed",
+    b"__signed__",
+    b"__inline",
+    b"__inline__",
+    b"__restrict",
+    b"__restrict__",
+    b"__volatile__",
+    b"__extension__",
+];
+
+/// Keywords that are never declared or used as identifiers
+pub const KEYWORDS: &[&[u8]] = &[
+    b"break",
+    b"case",
+    b"continue",
+    b"default",
+    b"do",
+    b"else",
+    b"for",
+    b"goto",
+    b"if",
+    b"return",
+    b"sizeof",
+    b"switch",
+    b"while",
+    b"_Alignas",
+    b"_Alignof",
+    b"_Generic",
+    b"_Static_assert",
+    b"alignas",
+    b"alignof",
+    b"false",
+    b"nullptr",
+    b"static_assert",
+    b"true",
+    b"typeof",
+    b"typeof_unqual",
+    b"asm",
+    b"_BitInt",
+];
+
+/// Keywords followed by a parenthesized group that contains no identifier uses
+const SKIPPED_GROUPS: &[&[u8]] = &[
+    b"__attribute__",
+    b"__declspec",
+    b"asm",
+    b"__asm__",
+    b"__asm",
+];
+
+/// Common names of the C library, assumed declared when the input includes a header
+const LIBRARY_NAMES: &[&[u8]] = &[
+    b"printf",
+    b"fprintf",
+    b"sprintf",
+    b"snprintf",
+    b"puts",
+    b"putchar",
+    b"scanf",
+    b"sscanf",
+    b"malloc",
+    b"calloc",
+    b"realloc",
+    b"free",
+    b"memcpy",
+    b"memmove",
+    b"memset",
+    b"memcmp",
+    b"strlen",
+    b"strcmp",
+    b"strncmp",
+    b"strcpy",
+    b"strncpy",
+    b"strcat",
+    b"strchr",
+    b"strstr",
+    b"exit",
+    b"abort",
+    b"assert",
+    b"atoi",
+    b"NULL",
+    b"EOF",
+    b"FILE",
+    b"stdin",
+    b"stdout",
+    b"stderr",
+    b"size_t",
+    b"ssize_t",
+    b"ptrdiff_t",
+    b"intptr_t",
+    b"uintptr_t",
+    b"int8_t",
+    b"int16_t",
+    b"int32_t",
+    b"int64_t",
+    b"uint8_t",
+    b"uint16_t",
+    b"uint32_t",
+    b"uint64_t",
+    b"va_list",
+    b"va_start",
+    b"va_end",
+    b"va_arg",
+    b"va_copy",
+    b"offsetof",
+    b"errno",
+    b"INT_MAX",
+    b"INT_MIN",
+    b"UINT_MAX",
+    b"CHAR_BIT",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Object,
+    Function,
+    Type,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScopeKind {
+    Block,
+    /// The members of a `struct` or `union`, which are not visible as identifiers
+    Record,
+    /// The enumerators of an `enum`, declared in the enclosing scope
+    Enum,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Expressions and statements, identifiers are uses
+    Statement,
+    /// The specifiers of a declaration, waiting for a declarator
+    Specifiers,
+    /// After the name of a declarator
+    Declared,
+    /// The initializer of a declarator, up to the next `,` or `;` outside of brackets
+    Initializer,
+}
+
+#[derive(Debug)]
+struct Scope {
+    kind: ScopeKind,
+    names: Vec<(Vec<u8>, Kind)>,
+    /// Where declarations are inserted into the scope
+    insert_at: usize,
+    /// The state to continue with after the scope, for `struct S { ... } s;`
+    resume: State,
+    /// The kind of the declarators after the scope, for `typedef struct { ... } T;`
+    resume_kind: Kind,
+}
+
+/// The scopes and declarations seen so far, and the edits to make
+struct Resolver<'a, R> {
+    input: &'a [u8],
+    tokens: Vec<Token>,
+    rand: &'a mut R,
+    scopes: Vec<Scope>,
+    macros: Vec<Vec<u8>>,
+    has_include: bool,
+    /// The declaration each undeclared name was redirected to
+    renamed: HashMap<Vec<u8>, Vec<u8>>,
+    /// The current declarator, declared once its size and initializer are resolved
+    declarator: Option<(&'a [u8], Kind)>,
+    edits: Vec<(usize, usize, Vec<u8>)>,
+}
+
+impl<'a, R: Rand> Resolver<'a, R> {
+    fn new(input: &'a [u8], rand: &'a mut R) -> Self {
+        let mut macros = vec![];
+        let mut has_include = false;
+        let mut tokens = vec![];
+        for token in lex(input) {
+            match token.kind {
+                TokenKind::Directive => {
+                    let directive = &input[token.start + 1..token.end];
+                    let mut words = directive
+                        .split(|b| !(b.is_ascii_alphanumeric() || *b == b'_'))
+                        .filter(|w| !w.is_empty());
+                    match words.next() {
+                        Some(b"include") => has_include = true,
+                        Some(b"define") => macros.extend(words.next().map(<[u8]>::to_vec)),
+                        _ => {}
+                    }
+                }
+                TokenKind::Comment => {}
+                _ => tokens.push(token),
+            }
+        }
+        Self {
+            input,
+            tokens,
+            rand,
+            scopes: vec![Scope {
+                kind: ScopeKind::Block,
+                names: vec![],
+                insert_at: 0,
+                resume: State::Statement,
+                resume_kind: Kind::Object,
+            }],
+            macros,
+            has_include,
+            renamed: HashMap::new(),
+            declarator: None,
+            edits: vec![],
+        }
+    }
+
+    fn text(&self, i: usize) -> &'a [u8] {
+        self.tokens
+            .get(i)
+            .map_or(&[], |t| &self.input[t.start..t.end])
+    }
+
+    fn is(&self, i: usize, text: &[u8]) -> bool {
+        self.text(i) == text
+    }
+
+    fn is_identifier(&self, i: usize) -> bool {
+        self.tokens
+            .get(i)
+            .is_some_and(|t| t.kind == TokenKind::Identifier)
+            && !SPECIFIERS.contains(&self.text(i))
+            && !KEYWORDS.contains(&self.text(i))
+    }
+
+    fn lookup(&self, name: &[u8]) -> Option<Kind> {
+        self.scopes
+            .iter()
+            .rev()
+            .filter(|s| s.kind == ScopeKind::Block)
+            .flat_map(|s| s.names.iter().rev())
+            .find(|(n, _)| n == name)
+            .map(|&(_, kind)| kind)
+    }
+
+    /// Builtins, macros and (with an `#include`) library names, which are never rewritten
+    fn is_external(&self, name: &[u8]) -> bool {
+        name.starts_with(b"__")
+            || self.macros.iter().any(|m| m == name)
+            || (self.has_include && LIBRARY_NAMES.contains(&name))
+    }
+
+    fn is_type_name(&self, i: usize) -> bool {
+        SPECIFIERS.contains(&self.text(i))
+            || (self.is_identifier(i) && self.lookup(self.text(i)) == Some(Kind::Type))
+    }
+
+    /// An external name in specifier position, taken to be a type as in `va_list ap;` or
+    /// `FILE *f;`
+    fn is_external_type(&self, i: usize) -> bool {
+        self.is_identifier(i)
+            && self.is_external(self.text(i))
+            && (self.is_identifier(i + 1) || self.is(i + 1, b"*"))
+    }
+
+    /// The innermost scope declarations are visible from
+    fn declaring_scope(&mut self) -> &mut Scope {
+        let index = self
+            .scopes
+            .iter()
+            .rposition(|s| s.kind != ScopeKind::Enum)
+            .unwrap();
+        &mut self.scopes[index]
+    }
+
+    fn declare(&mut self, name: &[u8], kind: Kind) {
+        self.declaring_scope().names.push((name.to_vec(), kind));
+    }
+
+    fn end_declarator(&mut self) {
+        if let Some((name, kind)) = self.declarator.take() {
+            self.declare(name, kind);
+        }
+    }
+
+    /// The visible declarations of `kind`, each name once
+    fn visible(&self, kind: Kind) -> Vec<Vec<u8>> {
+        let mut names: Vec<Vec<u8>> = vec![];
+        for scope in self.scopes.iter().filter(|s| s.kind == ScopeKind::Block) {
+            for (name, k) in &scope.names {
+                if *k == kind && !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        // Shadowed names of another kind are not visible
+        names.retain(|name| self.lookup(name) == Some(kind));
+        names
+    }
+
+    fn replace(&mut self, i: usize, with: Vec<u8>) {
+        let token = self.tokens[i];
+        self.edits.push((token.start, token.end, with));
+    }
+
+    /// Redirects the undeclared identifier at `i` to a visible declaration, or declares it
+    fn resolve_use(&mut self, i: usize) {
+        let name = self.text(i);
+        if self.lookup(name).is_some() || self.is_external(name) {
+            return;
+        }
+        let kind = if self.is(i + 1, b"(") {
+            Kind::Function
+        } else {
+            Kind::Object
+        };
+        if let Some(target) = self.renamed.get(name) {
+            if self.lookup(target) == Some(kind) {
+                let target = target.clone();
+                self.replace(i, target);
+                return;
+            }
+        }
+
+        let candidates = self.visible(kind);
+        if !candidates.is_empty() && (kind == Kind::Function || self.rand.below(nonzero(4)) != 0) {
+            let target = candidates[self.rand.below(nonzero(candidates.len()))].clone();
+            self.renamed.insert(name.to_vec(), target.clone());
+            self.replace(i, target);
+        } else if kind == Kind::Object {
+            let scope = self.declaring_scope();
+            let declaration = if scope.insert_at == 0 {
+                [b"int ", name, b";\n"].concat()
+            } else {
+                [b" int ", name, b";"].concat()
+            };
+            let at = scope.insert_at;
+            scope.names.push((name.to_vec(), Kind::Object));
+            self.edits.push((at, at, declaration));
+        }
+    }
+
+    /// The index of the token closing the group opened at `open`
+    fn matching(&self, open: usize) -> usize {
+        let mut depth = 0;
+        for i in open..self.tokens.len() {
+            match self.text(i) {
+                b"(" | b"[" | b"{" => depth += 1,
+                b")" | b"]" | b"}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.tokens.len()
+    }
+
+    /// The names declared by the parameter list opened at `open`
+    fn parameters(&self, open: usize, close: usize) -> Vec<Vec<u8>> {
+        let mut names = vec![];
+        let mut segment = vec![];
+        let mut has_type = false;
+        let mut depth = 0;
+        for i in open + 1..=close {
+            match self.text(i) {
+                b"(" | b"[" => depth += 1,
+                b")" | b"]" if depth > 0 => depth -= 1,
+                b"," | b")" if depth == 0 => {
+                    // A lone identifier is a type, as in `void f(size_t)`
+                    if has_type || segment.len() > 1 {
+                        names.extend(segment.pop());
+                    }
+                    segment.clear();
+                    has_type = false;
+                }
+                _ if self.is_type_name(i) => has_type = true,
+                _ if depth <= 1 && self.is_identifier(i) => {
+                    segment.push(self.text(i).to_vec());
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+
+    fn run(mut self) -> Vec<(usize, usize, Vec<u8>)> {
+        let mut state = State::Statement;
+        let mut at_statement_start = true;
+        let mut specifier_kind = Kind::Object;
+        // Brackets opened within an initializer or an array declarator
+        let mut nesting = 0usize;
+        // A `struct`, `union` or `enum` keyword whose body may follow, and whether its tag did
+        let mut tag_keyword: Option<(ScopeKind, bool)> = None;
+        // Within a `case` or `default` label
+        let mut in_case = false;
+
+        let mut i = 0;
+        while i < self.tokens.len() {
+            let text = self.text(i);
+            let start_of_statement = at_statement_start;
+            at_statement_start = false;
+
+            if SKIPPED_GROUPS.contains(&text) && self.is(i + 1, b"(") {
+                i = self.matching(i + 1) + 1;
+                at_statement_start = start_of_statement;
+                continue;
+            }
+            if text == b"[" && self.is(i + 1, b"[") {
+                // A C23 attribute
+                i = self.matching(i) + 1;
+                at_statement_start = start_of_statement;
+                continue;
+            }
+
+            if let Some((kind, tagged)) = tag_keyword.take() {
+                if !tagged && self.is_identifier(i) {
+                    // The tag, in its own namespace
+                    tag_keyword = Some((kind, true));
+                    i += 1;
+                    continue;
+                }
+                if text == b"{" {
+                    self.scopes.push(Scope {
+                        kind,
+                        names: vec![],
+                        insert_at: self.tokens[i].end,
+                        resume: State::Specifiers,
+                        resume_kind: specifier_kind,
+                    });
+                    state = State::Statement;
+                    at_statement_start = true;
+                    i += 1;
+                    continue;
+                }
+            }
+
+            let in_enum = self.scopes.last().unwrap().kind == ScopeKind::Enum;
+            match state {
+                State::Statement | State::Specifiers if text == b"}" => {
+                    // The last enumerator
+                    self.end_declarator();
+                    if self.scopes.len() > 1 {
+                        let scope = self.scopes.pop().unwrap();
+                        state = scope.resume;
+                        specifier_kind = scope.resume_kind;
+                    } else {
+                        state = State::Statement;
+                    }
+                    at_statement_start = state == State::Statement;
+                }
+                _ if in_enum && state != State::Initializer => {
+                    if self.is_identifier(i) {
+                        self.end_declarator();
+                        self.declarator = Some((text, Kind::Object));
+                    } else if text == b"," {
+                        self.end_declarator();
+                    } else if text == b"=" {
+                        state = State::Initializer;
+                        nesting = 0;
+                    }
+                }
+                State::Statement => {
+                    if text == b"struct" || text == b"union" || text == b"enum" {
+                        tag_keyword = Some((
+                            if text == b"enum" {
+                                ScopeKind::Enum
+                            } else {
+                                ScopeKind::Record
+                            },
+                            false,
+                        ));
+                    }
+                    if start_of_statement && self.is_type_name(i) {
+                        state = State::Specifiers;
+                        specifier_kind = if text == b"typedef" {
+                            Kind::Type
+                        } else {
+                            Kind::Object
+                        };
+                    } else if start_of_statement && self.is_external_type(i) {
+                        state = State::Specifiers;
+                        specifier_kind = Kind::Object;
+                    } else if start_of_statement
+                        && self.is_identifier(i)
+                        && self.is_identifier(i + 1)
+                        && !self.is_external(text)
+                    {
+                        // An unknown type name, as in `foo_t x;`
+                        let types = self.visible(Kind::Type);
+                        let replacement = if types.is_empty() {
+                            b"int".to_vec()
+                        } else {
+                            types[self.rand.below(nonzero(types.len()))].clone()
+                        };
+                        self.replace(i, replacement);
+                        state = State::Specifiers;
+                        specifier_kind = Kind::Object;
+                    } else if text == b"{" {
+                        self.scopes.push(Scope {
+                            kind: ScopeKind::Block,
+                            names: vec![],
+                            insert_at: self.tokens[i].end,
+                            resume: State::Statement,
+                            resume_kind: Kind::Object,
+                        });
+                        at_statement_start = true;
+                    } else if text == b";" {
+                        at_statement_start = true;
+                    } else if text == b"(" && i > 0 && self.is(i - 1, b"for") {
+                        at_statement_start = true;
+                    } else if text == b"case" || text == b"default" {
+                        in_case = true;
+                    } else if text == b":" && in_case {
+                        in_case = false;
+                        at_statement_start = true;
+                    } else if text == b":" && i > 0 && self.is_identifier(i - 1) {
+                        // After a label
+                        at_statement_start =
+                            i < 2 || matches!(self.text(i - 2), b";" | b"{" | b"}");
+                    } else if self.is_identifier(i) {
+                        let is_member = i > 0 && matches!(self.text(i - 1), b"." | b"->" | b"goto");
+                        let is_label = start_of_statement && self.is(i + 1, b":");
+                        if !is_member && !is_label {
+                            self.resolve_use(i);
+                        }
+                    }
+                }
+                State::Specifiers => {
+                    if text == b"typedef" {
+                        specifier_kind = Kind::Type;
+                    } else if text == b"struct" || text == b"union" || text == b"enum" {
+                        tag_keyword = Some((
+                            if text == b"enum" {
+                                ScopeKind::Enum
+                            } else {
+                                ScopeKind::Record
+                            },
+                            false,
+                        ));
+                    } else if text == b";" {
+                        state = State::Statement;
+                        at_statement_start = true;
+                    } else if self.is_identifier(i)
+                        && !(self.is_type_name(i) && self.is_identifier(i + 1))
+                        && !self.is_external_type(i)
+                    {
+                        if self.is(i + 1, b"(") {
+                            // A function declarator
+                            self.declare(text, Kind::Function);
+                            let close = self.matching(i + 1);
+                            let parameters = self.parameters(i + 1, close);
+                            if self.is(close + 1, b"{") {
+                                self.scopes.push(Scope {
+                                    kind: ScopeKind::Block,
+                                    names: parameters
+                                        .into_iter()
+                                        .map(|p| (p, Kind::Object))
+                                        .collect(),
+                                    insert_at: self.tokens[close + 1].end,
+                                    resume: State::Statement,
+                                    resume_kind: Kind::Object,
+                                });
+                                state = State::Statement;
+                                at_statement_start = true;
+                                i = close + 2;
+                                continue;
+                            }
+                            i = close;
+                        } else {
+                            self.declarator = Some((text, specifier_kind));
+                        }
+                        state = State::Declared;
+                    }
+                }
+                State::Declared => match text {
+                    b"=" => {
+                        state = State::Initializer;
+                        nesting = 0;
+                    }
+                    b"," => {
+                        self.end_declarator();
+                        state = State::Specifiers;
+                    }
+                    b";" => {
+                        self.end_declarator();
+                        state = State::Statement;
+                        at_statement_start = true;
+                    }
+                    b"(" => {
+                        // The parameters of a function pointer
+                        i = self.matching(i);
+                    }
+                    b"[" => {
+                        state = State::Initializer;
+                        nesting = 1;
+                    }
+                    _ => {}
+                },
+                State::Initializer => match text {
+                    b"(" | b"[" | b"{" => nesting += 1,
+                    b")" | b"]" | b"}" if nesting > 0 => {
+                        nesting -= 1;
+                        if nesting == 0 && text == b"]" {
+                            // The end of an array declarator
+                            state = State::Declared;
+                        }
+                    }
+                    b"," if nesting == 0 => {
+                        self.end_declarator();
+                        state = if in_enum {
+                            State::Statement
+                        } else {
+                            State::Specifiers
+                        };
+                    }
+                    b";" if nesting == 0 => {
+                        self.end_declarator();
+                        state = State::Statement;
+                        at_statement_start = true;
+                    }
+                    b"}" if in_enum => {
+                        self.end_declarator();
+                        let scope = self.scopes.pop().unwrap();
+                        state = scope.resume;
+                        specifier_kind = scope.resume_kind;
+                    }
+                    _ if self.is_identifier(i) => {
+                        let is_member = i > 0 && matches!(self.text(i - 1), b"." | b"->");
+                        if !is_member {
+                            self.resolve_use(i);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+            i += 1;
+        }
+        self.edits
+    }
+}
+
+fn nonzero(n: usize) -> NonZeroUsize {
+    NonZeroUsize::new(n).unwrap()
+}
+
+/// Rewrites the uses of undeclared identifiers in `input` to visible declarations of the same
+/// kind, or inserts an `int` declaration, and replaces unknown type names.
+///
+/// Returns `None` if every identifier already resolves.
+pub fn fix_identifiers<R: Rand>(rand: &mut R, input: &[u8]) -> Option<Vec<u8>> {
+    let mut edits = Resolver::new(input, rand).run();
+    if edits.is_empty() {
+        return None;
+    }
+    // Insertions before the replacement of the token they precede
+    edits.sort_by_key(|&(start, end, _)| (start, end));
+    let mut fixed = Vec::with_capacity(input.len());
+    let mut pos = 0;
+    for (start, end, replacement) in edits {
+        fixed.extend_from_slice(&input[pos..start]);
+        fixed.extend_from_slice(&replacement);
+        pos = end;
+    }
+    fixed.extend_from_slice(&input[pos..]);
+    Some(fixed)
+}
+
+/// Makes identifier uses refer to declarations in scope, see [`fix_identifiers`].
+///
+/// Pass it as the mutator of the `*-posthavoc` stages (with one post-mutation) to fix every
+/// generated input, or merge it into the havoc mutations.
+#[derive(Debug, Clone)]
+pub struct IdentifierConsistencyMutator {
+    name: Cow<'static, str>,
+}
+
+impl IdentifierConsistencyMutator {
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("IdentifierConsistencyMutator"),
+        }
+    }
+}
+
+impl Default for IdentifierConsistencyMutator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Named for IdentifierConsistencyMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<BytesInput, S> for IdentifierConsistencyMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut BytesInput) -> Result<MutationResult, Error> {
+        match fix_identifiers(state.rand_mut(), input.mutator_bytes()) {
+            Some(fixed) => {
+                *input = BytesInput::new(fixed);
+                Ok(MutationResult::Mutated)
+            }
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _new_corpus_id: Option<libafl::corpus::CorpusId>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::rands::StdRand;
+
+    use super::fix_identifiers;
+    use crate::config::seeds::{SeedsConfig, ValidCorpusSeedsConfig};
+
+    fn fix(input: &str) -> Option<String> {
+        let mut rand = StdRand::with_seed(0);
+        fix_identifiers(&mut rand, input.as_bytes()).map(|f| String::from_utf8(f).unwrap())
+    }
+
+    #[test]
+    fn valid_seeds_are_unchanged() {
+        for seed in ValidCorpusSeedsConfig::get_seeds() {
+            let mut rand = StdRand::with_seed(0);
+            let fixed = fix_identifiers(&mut rand, &seed);
+            assert!(
+                fixed.is_none(),
+                "changed a valid seed:\n{}",
+                String::from_utf8_lossy(&fixed.unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn typedef_of_record_declares_type() {
+        assert_eq!(
+            fix("typedef struct { int a; } T; T t; int main(void) { return t.a; }"),
+            None
+        );
+    }
+
+    #[test]
+    fn library_types_are_kept() {
+        assert_eq!(
+            fix("#include <stdio.h>\nint main(void) { FILE *f = stdin; va_list ap; return f == 0; }"),
+            None
+        );
+    }
+
+    #[test]
+    fn declarator_is_not_visible_in_its_size() {
+        assert_eq!(
+            fix("int main(void) { int a[n]; return a[0]; }").as_deref(),
+            Some("int main(void) { int n; int a[n]; return a[0]; }")
+        );
+    }
+
+    #[test]
+    fn enumerator_is_not_visible_in_its_value() {
+        for seed in 0..16 {
+            let mut rand = StdRand::with_seed(seed);
+            let fixed = fix_identifiers(&mut rand, b"enum E { A, B = C };").unwrap();
+            assert!(!String::from_utf8(fixed).unwrap().contains("B = B"));
+        }
+    }
+
+    #[test]
+    fn declarator_is_not_visible_in_its_initializer() {
+        assert_eq!(
+            fix("int main(void) { int x = y; return x; }").as_deref(),
+            Some("int main(void) { int y; int x = y; return x; }")
+        );
+    }
+}
@@ -0,0 +1,427 @@
+//! Mutators that rewrite single C literals, leaving the rest of the program intact.
+//!
+//! Literals are found with the [`lex`](crate::tokenizer::lex)er and replaced by boundary values
+//! (for clang's constant evaluation) or by slightly malformed spellings (for its lexer).
+
+use std::{borrow::Cow, num::NonZeroUsize, ops::Range};
+
+use libafl::{
+    inputs::{BytesInput, HasMutatorBytes},
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+    Named,
+};
+
+use crate::tokenizer::{lex, TokenKind};
+
+/// Integer values at the limits of C's integer types, and just beyond
+const INTEGERS: &[&str] = &[
+    "0",
+    "1",
+    "127",
+    "128",
+    "255",
+    "256",
+    "32767",
+    "32768",
+    "65535",
+    "65536",
+    "2147483647",
+    "2147483648",
+    "4294967295",
+    "4294967296",
+    "9223372036854775807",
+    "9223372036854775808",
+    "18446744073709551615",
+    "18446744073709551616",
+    "340282366920938463463374607431768211455",
+    "0x7f",
+    "0x80",
+    "0xff",
+    "0x7fffffff",
+    "0x80000000",
+    "0xffffffff",
+    "0x7fffffffffffffff",
+    "0x8000000000000000",
+    "0xffffffffffffffff",
+    "0x10000000000000000",
+    "0177777",
+    "037777777777",
+    "01777777777777777777777",
+    "0b1",
+    "0b11111111111111111111111111111111",
+    "1'000'000",
+];
+
+/// Integer spellings clang should reject
+const MALFORMED_INTEGERS: &[&str] = &[
+    "08",
+    "09",
+    "0x",
+    "0X",
+    "0xg",
+    "0b",
+    "0b2",
+    "0B",
+    "1'",
+    "1''0",
+    "'1",
+    "0x'1",
+    "1_000",
+    "1__",
+    "0xffffffffffffffffffffffffffffffffff",
+    "99999999999999999999999999999999999999999",
+];
+
+/// Integer suffixes, including some that only exist in newer standards, C++ or not at all
+const INTEGER_SUFFIXES: &[&str] = &[
+    "", "u", "U", "l", "L", "ul", "UL", "lu", "ll", "LL", "ull", "ULL", "llu", "lL", "Ll", "uu",
+    "lll", "wb", "WB", "uwb", "UWB", "Wb", "z", "Z", "uz", "i", "j", "f", "d", "e",
+];
+
+/// Floating values at the limits of `float`, `double` and `long double`
+const FLOATS: &[&str] = &[
+    "0.0",
+    "0.",
+    ".0",
+    "-0.0",
+    "1e0",
+    "3.40282347e38",
+    "3.40282357e38",
+    "1.17549435e-38",
+    "1.4e-45",
+    "1e-46",
+    "1.7976931348623157e308",
+    "1.7976931348623159e308",
+    "1e309",
+    "2.2250738585072014e-308",
+    "4.9e-324",
+    "1e-325",
+    "1.18973149535723176502e4932",
+    "1e4933",
+    "1e-4951",
+    "0x1p-1074",
+    "0x1p-1075",
+    "0x1.fffffffffffffp1023",
+    "0x1p1024",
+    "0x1.0p0",
+    "0x.8p1",
+    "1e99999999999999999999",
+    "0.000000000000000000000000000000000000000000000000000000000000000001",
+    "1'000.000'1",
+];
+
+/// Floating spellings clang should reject
+const MALFORMED_FLOATS: &[&str] = &[
+    "1e", "1e+", "1e-", "1.0e+-1", "0x1.0", "0x.p1", "0x1p", "1.0.0", "1..0", "1p3", "0x1e3p",
+    "1e3.0", "1.e", "1'.0", "1.'0",
+];
+
+/// Floating suffixes, including decimal, extended and half precision types
+const FLOAT_SUFFIXES: &[&str] = &[
+    "", "f", "F", "l", "L", "f16", "F16", "bf16", "BF16", "f32", "f64", "f128", "f32x", "q", "Q",
+    "w", "df", "DF", "dd", "DD", "dl", "DL", "i", "j", "fi", "ff", "fl", "lf", "u", "ll",
+];
+
+/// Escape sequences and raw bytes to insert into character and string literals
+const ESCAPES: &[&[u8]] = &[
+    b"\\0",
+    b"\\7",
+    b"\\377",
+    b"\\400",
+    b"\\777",
+    b"\\x",
+    b"\\xff",
+    b"\\x100",
+    b"\\xffffffff",
+    b"\\x100000000",
+    b"\\u",
+    b"\\u00e9",
+    b"\\u0041",
+    b"\\u0024",
+    b"\\uD800",
+    b"\\uDFFF",
+    b"\\U0010FFFF",
+    b"\\U00110000",
+    b"\\Uffffffff",
+    b"\\x{41}",
+    b"\\o{777}",
+    b"\\u{1F600}",
+    b"\\N{LATIN SMALL LETTER A}",
+    b"\\N{NOT A NAME}",
+    b"\\q",
+    b"\\e",
+    b"\\%",
+    b"\\",
+    b"\\\n",
+    b"\n",
+    b"\0",
+    b"\xc3\xa9",
+    b"\xf0\x9f\x98\x80",
+    b"\xff",
+    b"\xc3",
+    b"\xc0\xaf",
+    b"\xed\xa0\x80",
+    b"??/",
+    b"??'",
+    b"\"",
+    b"'",
+];
+
+/// Whole character literals, valid and malformed
+const CHARS: &[&str] = &[
+    "'\\0'",
+    "''",
+    "'ab'",
+    "'abcd'",
+    "'abcde'",
+    "'\\377'",
+    "'\\400'",
+    "'\\xff'",
+    "'\\x100'",
+    "'é'",
+    "u8'é'",
+    "u8'\\xff'",
+    "u'\\U00010000'",
+    "U'\\U0010FFFF'",
+    "U'\\U00110000'",
+    "L'\\xffffffff'",
+    "L'ab'",
+    "'a",
+    "'\\'",
+    "'\\q'",
+    "'\\u00e9'",
+    "'\\uD800'",
+];
+
+/// Whole string literals, valid and malformed, including raw strings
+const STRINGS: &[&str] = &[
+    "\"\"",
+    "\"\\0\"",
+    "\"\\xff\"",
+    "\"\\x\"",
+    "\"abc",
+    "\"\\\"",
+    "u8\"é\"",
+    "u8\"\\xff\"",
+    "L\"\\U0010FFFF\"",
+    "u\"\\uD800\"",
+    "\"??/\"",
+    "\"a\" L\"b\"",
+    "u\"a\" U\"b\"",
+    "u8\"a\" L\"b\"",
+    "R\"(a)\"",
+    "R\"x(a)\")x\"",
+    "R\"abc(a)abc\"",
+    "R\"abc(a)ab\"",
+    "R\"abc(a)cba\"",
+    "R\"(a\"",
+    "R\"12345678901234567(a)12345678901234567\"",
+    "R\"a b(a)a b\"",
+    "R\"\\(a)\\\"",
+    "LR\"(a)\"",
+    "u8R\"(\\xff)\"",
+];
+
+const ENCODING_PREFIXES: &[&str] = &["", "L", "u", "U", "u8"];
+
+/// The literals of C, as told apart by the mutators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralKind {
+    Integer,
+    Floating,
+    Char,
+    String,
+}
+
+impl LiteralKind {
+    fn of(token_kind: TokenKind, spelling: &[u8]) -> Option<Self> {
+        match token_kind {
+            TokenKind::Number if is_floating(spelling) => Some(Self::Floating),
+            TokenKind::Number => Some(Self::Integer),
+            TokenKind::CharLiteral => Some(Self::Char),
+            TokenKind::StringLiteral => Some(Self::String),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the preprocessing number `spelling` is a floating constant
+pub fn is_floating(spelling: &[u8]) -> bool {
+    if spelling.starts_with(b"0x") || spelling.starts_with(b"0X") {
+        spelling
+            .iter()
+            .any(|&b| b == b'.' || b == b'p' || b == b'P')
+    } else {
+        spelling
+            .iter()
+            .any(|&b| b == b'.' || b == b'e' || b == b'E')
+    }
+}
+
+/// The byte ranges of all literals of `kind` in `input`
+pub fn literals(input: &[u8], kind: LiteralKind) -> Vec<Range<usize>> {
+    lex(input)
+        .into_iter()
+        .filter(|t| LiteralKind::of(t.kind, &input[t.start..t.end]) == Some(kind))
+        .map(|t| t.start..t.end)
+        .collect()
+}
+
+/// Length of the suffix of the number `spelling`, the longest of `suffixes` it ends with
+fn suffix_len(spelling: &[u8], suffixes: &[&str]) -> usize {
+    // Up to the exponent of a hexadecimal float, `b`, `d`, `e` and `f` are digits
+    let hex_digits_at_end = (spelling.starts_with(b"0x") || spelling.starts_with(b"0X"))
+        && !spelling.iter().any(|&b| b == b'p' || b == b'P');
+    suffixes
+        .iter()
+        .map(|s| s.as_bytes())
+        .filter(|s| spelling.len() > s.len() && spelling.ends_with(s))
+        .filter(|s| !hex_digits_at_end || !s.last().is_some_and(u8::is_ascii_hexdigit))
+        .map(<[u8]>::len)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Length of the encoding prefix of a character or string literal, including a raw `R`
+fn prefix_len(spelling: &[u8]) -> usize {
+    spelling
+        .iter()
+        .position(|&b| b == b'"' || b == b'\'')
+        .unwrap_or(0)
+}
+
+fn pick<'a, T: ?Sized, R: Rand>(rand: &mut R, from: &[&'a T]) -> &'a T {
+    from[rand.below(NonZeroUsize::new(from.len()).unwrap())]
+}
+
+/// Replaces a random literal of one kind in a C program by a boundary value or a malformed
+/// variant, with a new suffix or encoding prefix, or with an escape sequence inserted
+#[derive(Debug, Clone)]
+pub struct CLiteralMutator {
+    kind: LiteralKind,
+    name: Cow<'static, str>,
+}
+
+impl CLiteralMutator {
+    pub fn new(kind: LiteralKind) -> Self {
+        Self {
+            kind,
+            name: Cow::Owned(format!("CLiteralMutator<{kind:?}>")),
+        }
+    }
+
+    /// The new spelling of the number `spelling`
+    fn mutate_number<R: Rand>(
+        rand: &mut R,
+        spelling: &[u8],
+        values: &[&str],
+        malformed: &[&str],
+        suffixes: &[&str],
+    ) -> Vec<u8> {
+        let (body, suffix) = spelling.split_at(spelling.len() - suffix_len(spelling, suffixes));
+        match rand.below(NonZeroUsize::new(4).unwrap()) {
+            0 | 1 => [pick(rand, values).as_bytes(), suffix].concat(),
+            2 => [body, pick(rand, suffixes).as_bytes()].concat(),
+            _ => [pick(rand, malformed).as_bytes(), suffix].concat(),
+        }
+    }
+
+    /// The new spelling of the character or string literal `spelling`
+    fn mutate_quoted<R: Rand>(rand: &mut R, spelling: &[u8], whole: &[&str]) -> Vec<u8> {
+        let prefix = prefix_len(spelling);
+        match rand.below(NonZeroUsize::new(5).unwrap()) {
+            0 | 1 => {
+                // Insert between the quotes, or right after the opening one if unterminated
+                let body = prefix + 1..spelling.len().saturating_sub(1).max(prefix + 1);
+                let at = body.start + rand.below(NonZeroUsize::new(body.len() + 1).unwrap());
+                [&spelling[..at], pick(rand, ESCAPES), &spelling[at..]].concat()
+            }
+            2 => pick(rand, whole).as_bytes().to_vec(),
+            3 => {
+                // Keep a raw string raw
+                let raw = usize::from(spelling[..prefix].ends_with(b"R"));
+                let new_prefix = pick(rand, ENCODING_PREFIXES).as_bytes();
+                [new_prefix, &spelling[prefix - raw..]].concat()
+            }
+            // Unterminated
+            _ => spelling[..spelling.len() - 1].to_vec(),
+        }
+    }
+}
+
+impl Named for CLiteralMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<BytesInput, S> for CLiteralMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut BytesInput) -> Result<MutationResult, Error> {
+        let bytes = input.mutator_bytes();
+        let candidates = literals(bytes, self.kind);
+        if candidates.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let rand = state.rand_mut();
+        let range = candidates[rand.below(NonZeroUsize::new(candidates.len()).unwrap())].clone();
+        let spelling = &bytes[range.clone()];
+
+        let replacement = match self.kind {
+            LiteralKind::Integer => Self::mutate_number(
+                rand,
+                spelling,
+                INTEGERS,
+                MALFORMED_INTEGERS,
+                INTEGER_SUFFIXES,
+            ),
+            LiteralKind::Floating => {
+                Self::mutate_number(rand, spelling, FLOATS, MALFORMED_FLOATS, FLOAT_SUFFIXES)
+            }
+            LiteralKind::Char => Self::mutate_quoted(rand, spelling, CHARS),
+            LiteralKind::String => Self::mutate_quoted(rand, spelling, STRINGS),
+        };
+        if replacement == spelling {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let mut mutated = bytes.to_vec();
+        mutated.splice(range, replacement);
+        *input = BytesInput::new(mutated);
+        Ok(MutationResult::Mutated)
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _new_corpus_id: Option<libafl::corpus::CorpusId>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The tuple of [`c_literal_mutations`]
+pub type CLiteralMutationsType = tuple_list_type!(
+    CLiteralMutator,
+    CLiteralMutator,
+    CLiteralMutator,
+    CLiteralMutator
+);
+
+/// One [`CLiteralMutator`] per kind of literal, to merge with the havoc mutations
+pub fn c_literal_mutations() -> CLiteralMutationsType {
+    tuple_list!(
+        CLiteralMutator::new(LiteralKind::Integer),
+        CLiteralMutator::new(LiteralKind::Floating),
+        CLiteralMutator::new(LiteralKind::Char),
+        CLiteralMutator::new(LiteralKind::String),
+    )
+}
@@ -0,0 +1,14 @@
+pub mod focus;
+pub mod identifiers;
+pub mod literal;
+pub mod splice;
+pub mod transform;
+
+#[allow(unused_imports)]
+pub use {
+    focus::{diagnostic_focus_mutations, DiagnosticFocusMutator},
+    identifiers::IdentifierConsistencyMutator,
+    literal::{c_literal_mutations, CLiteralMutator},
+    splice::{block_splice_mutations, BlockInsertMutator, BlockReplaceMutator},
+    transform::{valid_transformations, ValidTransformMutator},
+};
@@ -0,0 +1,331 @@
+//! Crossover that exchanges whole bracket groups and statements between corpus entries.
+//!
+//! Byte-level splicing cuts programs at arbitrary offsets, which almost always leaves an
+//! unbalanced bracket or half a token behind. These mutators only cut at the boundaries of
+//! balanced `{...}`, `(...)` and `[...]` groups and of statements ending in `;` or `}`, as found
+//! by the [`lex`](crate::tokenizer::lex)er, so the lexical and bracket structure survives.
+
+use std::{borrow::Cow, num::NonZeroUsize, ops::Range};
+
+use libafl::{
+    corpus::Corpus,
+    inputs::{BytesInput, HasMutatorBytes},
+    mutators::{MutationResult, Mutator},
+    random_corpus_id_with_disabled,
+    state::{HasCorpus, HasMaxSize, HasRand},
+    Error,
+};
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+    Named,
+};
+
+use crate::tokenizer::{lex, TokenKind};
+
+/// What a [`Fragment`] spans, fragments are only exchanged for ones of the same kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentKind {
+    Braces,
+    /// The braces of an initializer, as in `int a[] = { ... };`, which hold no statements
+    Initializer,
+    Parens,
+    Brackets,
+    /// A declaration or statement, at file scope or within braces
+    Statement {
+        top_level: bool,
+    },
+}
+
+/// A balanced group or statement as a byte range into the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub kind: FragmentKind,
+    pub range: Range<usize>,
+}
+
+/// An open bracket, with the first token of the statement in progress for braces
+struct Frame {
+    open: u8,
+    /// Index of the opening token
+    index: usize,
+    /// Braces of an initializer, or nested in one
+    initializer: bool,
+    statement: Option<usize>,
+}
+
+/// The balanced groups and complete statements of `input`, unbalanced brackets are ignored
+pub fn fragments(input: &[u8]) -> Vec<Fragment> {
+    let tokens = lex(input)
+        .into_iter()
+        .filter(|t| !matches!(t.kind, TokenKind::Comment | TokenKind::Directive))
+        .collect::<Vec<_>>();
+    let text = |i: usize| tokens.get(i).map_or(&b""[..], |t| &input[t.start..t.end]);
+
+    let mut fragments = vec![];
+    let mut frames = vec![Frame {
+        open: b'{',
+        index: 0,
+        initializer: false,
+        statement: None,
+    }];
+    for (i, token) in tokens.iter().enumerate() {
+        let top = frames.last_mut().unwrap();
+        let in_braces = top.open == b'{' && !top.initializer;
+        if in_braces && top.statement.is_none() && !matches!(text(i), b"}" | b";") {
+            top.statement = Some(i);
+        }
+        match text(i) {
+            open @ (b"{" | b"(" | b"[") => {
+                let initializer = open == b"{"
+                    && (text(i.wrapping_sub(1)) == b"="
+                        || frames.last().is_some_and(|f| f.initializer));
+                frames.push(Frame {
+                    open: open[0],
+                    index: i,
+                    initializer,
+                    statement: None,
+                })
+            }
+            close @ (b"}" | b")" | b"]") => {
+                let expected = match close[0] {
+                    b'}' => b'{',
+                    b')' => b'(',
+                    _ => b'[',
+                };
+                // Ignore unbalanced brackets, as well as the closing brace of the file scope
+                if frames.len() < 2 || frames.last().unwrap().open != expected {
+                    continue;
+                }
+                let frame = frames.pop().unwrap();
+                let kind = match expected {
+                    b'{' if frame.initializer => FragmentKind::Initializer,
+                    b'{' => FragmentKind::Braces,
+                    b'(' => FragmentKind::Parens,
+                    _ => FragmentKind::Brackets,
+                };
+                fragments.push(Fragment {
+                    kind,
+                    range: tokens[frame.index].start..token.end,
+                });
+
+                // A block ends its statement unless the statement goes on, as in
+                // `struct S { ... } s;`, `int a[] = { ... };` or `do { ... } while (x);`
+                let top_level = frames.len() == 1;
+                let parent = frames.last_mut().unwrap();
+                if let (b'{', b'{', Some(start)) = (expected, parent.open, parent.statement) {
+                    let declares_type = text(frame.index.wrapping_sub(1)) != b")"
+                        && (start..frame.index).any(|j| {
+                            matches!(text(j), b"struct" | b"union" | b"enum" | b"typedef")
+                        });
+                    let goes_on = declares_type
+                        || matches!(text(i + 1), b";" | b"," | b")" | b"=" | b"else")
+                        || (text(i + 1) == b"while" && text(start) == b"do");
+                    if !goes_on {
+                        fragments.push(Fragment {
+                            kind: FragmentKind::Statement { top_level },
+                            range: tokens[start].start..token.end,
+                        });
+                        parent.statement = None;
+                    }
+                }
+            }
+            b";" if in_braces => {
+                if let Some(start) = frames.last_mut().unwrap().statement.take() {
+                    fragments.push(Fragment {
+                        kind: FragmentKind::Statement {
+                            top_level: frames.len() == 1,
+                        },
+                        range: tokens[start].start..token.end,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    fragments
+}
+
+fn pick<'a, T, R: Rand>(rand: &mut R, from: &'a [T]) -> Option<&'a T> {
+    NonZeroUsize::new(from.len()).map(|len| &from[rand.below(len)])
+}
+
+/// The bytes of another random corpus entry, `None` if it is the current one
+fn other_input<S>(state: &mut S) -> Result<Option<Vec<u8>>, Error>
+where
+    S: HasCorpus<BytesInput> + HasRand,
+{
+    let id = random_corpus_id_with_disabled!(state.corpus(), state.rand_mut());
+    if state
+        .corpus()
+        .current()
+        .is_some_and(|current| current == id)
+    {
+        return Ok(None);
+    }
+    let mut testcase = state.corpus().get_from_all(id)?.borrow_mut();
+    Ok(Some(
+        testcase
+            .load_input(state.corpus())?
+            .mutator_bytes()
+            .to_vec(),
+    ))
+}
+
+/// Replaces a random balanced group or statement with one of the same kind from another
+/// corpus entry
+#[derive(Debug, Clone)]
+pub struct BlockReplaceMutator {
+    name: Cow<'static, str>,
+}
+
+impl BlockReplaceMutator {
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("BlockReplaceMutator"),
+        }
+    }
+}
+
+impl Default for BlockReplaceMutator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Named for BlockReplaceMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<BytesInput, S> for BlockReplaceMutator
+where
+    S: HasCorpus<BytesInput> + HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut BytesInput) -> Result<MutationResult, Error> {
+        let bytes = input.mutator_bytes();
+        let Some(target) = pick(state.rand_mut(), &fragments(bytes)).cloned() else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(other) = other_input(state)? else {
+            return Ok(MutationResult::Skipped);
+        };
+        let replacements = fragments(&other)
+            .into_iter()
+            .filter(|f| f.kind == target.kind)
+            .filter(|f| other[f.range.clone()] != bytes[target.range.clone()])
+            .collect::<Vec<_>>();
+        let Some(replacement) = pick(state.rand_mut(), &replacements) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let len = bytes.len() - target.range.len() + replacement.range.len();
+        if len > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+        let mut mutated = bytes.to_vec();
+        mutated.splice(
+            target.range,
+            other[replacement.range.clone()].iter().copied(),
+        );
+        *input = BytesInput::new(mutated);
+        Ok(MutationResult::Mutated)
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _new_corpus_id: Option<libafl::corpus::CorpusId>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Inserts a statement from another corpus entry before or after a random statement at the
+/// same level, i.e. at file scope or within a block
+#[derive(Debug, Clone)]
+pub struct BlockInsertMutator {
+    name: Cow<'static, str>,
+}
+
+impl BlockInsertMutator {
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("BlockInsertMutator"),
+        }
+    }
+}
+
+impl Default for BlockInsertMutator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Named for BlockInsertMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<BytesInput, S> for BlockInsertMutator
+where
+    S: HasCorpus<BytesInput> + HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut BytesInput) -> Result<MutationResult, Error> {
+        let bytes = input.mutator_bytes();
+        let statements = fragments(bytes)
+            .into_iter()
+            .filter(|f| matches!(f.kind, FragmentKind::Statement { .. }))
+            .collect::<Vec<_>>();
+        let Some(target) = pick(state.rand_mut(), &statements).cloned() else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(other) = other_input(state)? else {
+            return Ok(MutationResult::Skipped);
+        };
+        let insertions = fragments(&other)
+            .into_iter()
+            .filter(|f| f.kind == target.kind)
+            .collect::<Vec<_>>();
+        let Some(insertion) = pick(state.rand_mut(), &insertions) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        if bytes.len() + insertion.range.len() + 1 > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+        let statement = &other[insertion.range.clone()];
+        let mut mutated = bytes.to_vec();
+        if state.rand_mut().coinflip(0.5) {
+            mutated.splice(
+                target.range.start..target.range.start,
+                statement.iter().chain(b" ").copied(),
+            );
+        } else {
+            mutated.splice(
+                target.range.end..target.range.end,
+                b" ".iter().chain(statement).copied(),
+            );
+        }
+        *input = BytesInput::new(mutated);
+        Ok(MutationResult::Mutated)
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _new_corpus_id: Option<libafl::corpus::CorpusId>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The tuple of [`block_splice_mutations`]
+pub type BlockSpliceMutationsType = tuple_list_type!(BlockReplaceMutator, BlockInsertMutator);
+
+/// Structure-preserving crossover, to merge with [`havoc_mutations_no_crossover`](libafl::mutators::havoc_mutations_no_crossover)
+pub fn block_splice_mutations() -> BlockSpliceMutationsType {
+    tuple_list!(BlockReplaceMutator::new(), BlockInsertMutator::new())
+}
@@ -0,0 +1,641 @@
+//! Semantics-preserving transformations of valid C programs.
+//!
+//! Mutating a valid program byte by byte almost always breaks it, so clang's later stages only
+//! see the valid corpus entries themselves. These rewrites keep a valid program valid (and its
+//! behavior unchanged), but take different paths through clang. They only rely on the
+//! [`lex`](crate::tokenizer::lex)er and skip every construct they cannot rewrite safely.
+
+use std::{borrow::Cow, num::NonZeroUsize, ops::Range};
+
+use libafl::{
+    inputs::{BytesInput, HasMutatorBytes},
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+    Named,
+};
+
+use crate::{
+    mutators::{
+        identifiers::{KEYWORDS, SPECIFIERS},
+        literal::is_floating,
+        splice::{fragments, FragmentKind},
+    },
+    tokenizer::{lex, Token, TokenKind},
+};
+
+/// The rewrites of a [`ValidTransformMutator`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transformation {
+    /// Parenthesizes a literal, a returned expression or a condition
+    Parenthesize,
+    /// Casts a literal to its own type
+    Cast,
+    /// Returns a temporary holding the returned expression
+    Temporary,
+    /// Swaps two adjacent declarations that do not refer to each other
+    Reorder,
+    /// Rewrites a `for` loop without `continue` into a `while` loop
+    ForToWhile,
+    /// Wraps a statement that declares nothing into a block
+    Block,
+}
+
+/// A replacement of `range` in the input
+type Edit = (Range<usize>, Vec<u8>);
+
+/// Keywords other than [`SPECIFIERS`] that start a declaration
+const DECLARATION_STARTS: &[&[u8]] = &[
+    b"typeof",
+    b"typeof_unqual",
+    b"__typeof",
+    b"__typeof__",
+    b"_Alignas",
+    b"alignas",
+    b"__attribute__",
+];
+
+/// A function definition at file scope, with the token indices of its body's braces
+struct Function {
+    open: usize,
+    close: usize,
+    returns_void: bool,
+}
+
+/// The tokens of a program without comments and directives
+struct Program<'a> {
+    input: &'a [u8],
+    tokens: Vec<Token>,
+}
+
+impl<'a> Program<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        let tokens = lex(input)
+            .into_iter()
+            .filter(|t| !matches!(t.kind, TokenKind::Comment | TokenKind::Directive))
+            .collect();
+        Self { input, tokens }
+    }
+
+    fn text(&self, i: usize) -> &'a [u8] {
+        self.tokens
+            .get(i)
+            .map_or(&[], |t| &self.input[t.start..t.end])
+    }
+
+    /// The bytes from the start of token `first` to the end of token `last`
+    fn span(&self, first: usize, last: usize) -> Range<usize> {
+        self.tokens[first].start..self.tokens[last].end
+    }
+
+    /// The index of the token closing the group opened at `open`
+    fn matching(&self, open: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for i in open..self.tokens.len() {
+            match self.text(i) {
+                b"(" | b"[" | b"{" => depth += 1,
+                b")" | b"]" | b"}" => {
+                    depth = depth.checked_sub(1)?;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The index of the first `;` from `from` on outside of brackets
+    fn semicolon(&self, from: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for i in from..self.tokens.len() {
+            match self.text(i) {
+                b"(" | b"[" | b"{" => depth += 1,
+                b")" | b"]" | b"}" => depth = depth.checked_sub(1)?,
+                b";" if depth == 0 => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The token indices within the byte range `range`
+    fn tokens_in(&self, range: &Range<usize>) -> Range<usize> {
+        let first = self.tokens.partition_point(|t| t.start < range.start);
+        let end = self.tokens.partition_point(|t| t.end <= range.end);
+        first..end.max(first)
+    }
+
+    fn is_identifier(&self, i: usize) -> bool {
+        self.tokens[i].kind == TokenKind::Identifier
+            && !SPECIFIERS.contains(&self.text(i))
+            && !KEYWORDS.contains(&self.text(i))
+    }
+
+    /// The function definitions, found as braces at file scope that follow a `)`
+    fn functions(&self) -> Vec<Function> {
+        let mut functions = vec![];
+        // The first token of the declaration in progress
+        let mut start = 0;
+        let mut i = 0;
+        while i < self.tokens.len() {
+            match self.text(i) {
+                b"{" if i > 0 && self.text(i - 1) == b")" => {
+                    let Some(close) = self.matching(i) else {
+                        break;
+                    };
+                    // The specifiers end at the name before the first parenthesis
+                    let returns_void =
+                        (start..i)
+                            .find(|&j| self.text(j) == b"(")
+                            .is_some_and(|paren| {
+                                let has = |text: &[u8]| {
+                                    (start..paren.saturating_sub(1)).any(|j| self.text(j) == text)
+                                };
+                                has(b"void") && !has(b"*")
+                            });
+                    functions.push(Function {
+                        open: i,
+                        close,
+                        returns_void,
+                    });
+                    i = close;
+                    start = close + 1;
+                }
+                b"(" | b"[" | b"{" => {
+                    let Some(close) = self.matching(i) else {
+                        break;
+                    };
+                    i = close;
+                }
+                b";" => start = i + 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        functions
+    }
+
+    /// The bodies of `struct` and `union` definitions, as byte ranges
+    fn records(&self) -> Vec<Range<usize>> {
+        (1..self.tokens.len())
+            .filter(|&i| self.text(i) == b"{")
+            .filter(|&i| {
+                matches!(self.text(i - 1), b"struct" | b"union")
+                    || (i > 1 && matches!(self.text(i - 2), b"struct" | b"union"))
+            })
+            .filter_map(|i| Some(self.span(i, self.matching(i)?)))
+            .collect()
+    }
+}
+
+/// The C type of an unprefixed number or character literal, if it is simple to name
+fn literal_type(token: &Token, spelling: &[u8]) -> Option<&'static str> {
+    match token.kind {
+        // clang runs with `-xc++`, where a character literal is a `char` unless it holds
+        // several characters
+        TokenKind::CharLiteral if spelling.starts_with(b"'") => {
+            let content = &spelling[1..spelling.len().saturating_sub(1).max(1)];
+            match content {
+                [b'\\', b'u' | b'U', ..] => None,
+                [_] | [b'\\', ..] => Some("char"),
+                _ => Some("int"),
+            }
+        }
+        TokenKind::Number if spelling.contains(&b'\'') => None,
+        TokenKind::Number if is_floating(spelling) => {
+            let (ty, value) = match spelling.split_last()? {
+                (b'f' | b'F', value) => ("float", value),
+                (b'l' | b'L', value) => ("long double", value),
+                _ => ("double", spelling),
+            };
+            // Anything else is another suffix, or `f` is a hexadecimal digit
+            let (digits, exponent) = if value.starts_with(b"0x") || value.starts_with(b"0X") {
+                (&value[2..], b'p')
+            } else {
+                (value, b'e')
+            };
+            let (mantissa, power) = match digits
+                .iter()
+                .position(|b| b.to_ascii_lowercase() == exponent)
+            {
+                Some(at) => (&digits[..at], &digits[at + 1..]),
+                None if exponent == b'p' => return None,
+                None => (digits, &b"0"[..]),
+            };
+            let power = power
+                .strip_prefix(b"-")
+                .or(power.strip_prefix(b"+"))
+                .unwrap_or(power);
+            let mantissa_ok = mantissa.iter().all(|b| {
+                *b == b'.' || (exponent == b'p' && b.is_ascii_hexdigit()) || b.is_ascii_digit()
+            });
+            (mantissa_ok && !power.is_empty() && power.iter().all(u8::is_ascii_digit)).then_some(ty)
+        }
+        TokenKind::Number => {
+            let digits = spelling
+                .iter()
+                .position(|b| matches!(b, b'u' | b'U' | b'l' | b'L'))
+                .unwrap_or(spelling.len());
+            let (value, suffix) = spelling.split_at(digits);
+            let value = std::str::from_utf8(value).ok()?;
+            let suffix = suffix.to_ascii_lowercase();
+            let is_decimal = !value.starts_with('0') || value == "0";
+            let value = if is_decimal {
+                value.parse::<u64>().ok()?
+            } else if let Some(hex) = value
+                .strip_prefix("0x")
+                .or_else(|| value.strip_prefix("0X"))
+            {
+                u64::from_str_radix(hex, 16).ok()?
+            } else if value.starts_with("0b") || value.starts_with("0B") {
+                return None;
+            } else {
+                u64::from_str_radix(value, 8).ok()?
+            };
+            // Octal and hexadecimal constants may also have an unsigned type, so only name
+            // the types they share with decimal ones
+            let fits = |max: u64| value <= max;
+            match suffix.as_slice() {
+                b"" if fits(i32::MAX as u64) => Some("int"),
+                b"u" if is_decimal && fits(u32::MAX as u64) => Some("unsigned int"),
+                b"l" if is_decimal && fits(i64::MAX as u64) => Some("long"),
+                b"ul" | b"lu" if is_decimal => Some("unsigned long"),
+                b"ll" if is_decimal && fits(i64::MAX as u64) => Some("long long"),
+                b"ull" | b"llu" if is_decimal => Some("unsigned long long"),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Literals and returned expressions in parentheses, conditions in another pair
+fn parenthesize(program: &Program) -> Vec<Edit> {
+    let mut edits = vec![];
+    for (i, token) in program.tokens.iter().enumerate() {
+        let text = program.text(i);
+        let after_member = i > 0 && matches!(program.text(i - 1), b"." | b"->");
+        if matches!(token.kind, TokenKind::Number | TokenKind::CharLiteral) && !after_member {
+            edits.push((token.start..token.end, [b"(", text, b")"].concat()));
+        } else if text == b"return" {
+            if let Some(end) = program.semicolon(i + 1).filter(|&end| end > i + 1) {
+                let range = program.span(i + 1, end - 1);
+                let expression = &program.input[range.clone()];
+                edits.push((range, [b"(", expression, b")"].concat()));
+            }
+        } else if matches!(text, b"if" | b"while" | b"switch") && program.text(i + 1) == b"(" {
+            if let Some(close) = program.matching(i + 1) {
+                let range = program.span(i + 1, close);
+                let condition = &program.input[range.clone()];
+                edits.push((range, [b"(", condition, b")"].concat()));
+            }
+        }
+    }
+    edits
+}
+
+/// Literals cast to the type they already have
+fn cast(program: &Program) -> Vec<Edit> {
+    program
+        .tokens
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i == 0 || !matches!(program.text(i - 1), b"." | b"->"))
+        .filter_map(|(i, token)| {
+            let text = program.text(i);
+            let ty = literal_type(token, text)?;
+            // In `(int)1.5` the floating constant has to stay the operand of the outer cast to
+            // form an integer constant expression
+            if i > 0 && program.text(i - 1) == b")" && is_floating(text) {
+                return None;
+            }
+            Some((
+                token.start..token.end,
+                [b"((", ty.as_bytes(), b")", text, b")"].concat(),
+            ))
+        })
+        .collect()
+}
+
+/// `return x;` as `{ __auto_type t = (x); return t; }`
+fn temporary(program: &Program) -> Vec<Edit> {
+    let name = (0..)
+        .map(|n| format!("__tmp{n}"))
+        .find(|name| {
+            !program
+                .input
+                .windows(name.len())
+                .any(|w| w == name.as_bytes())
+        })
+        .unwrap();
+    let functions = program.functions();
+    let mut edits = vec![];
+    for i in 0..program.tokens.len() {
+        if program.text(i) != b"return" {
+            continue;
+        }
+        // A `void` function may return a `void` expression, which has no temporary
+        let in_function = functions.iter().find(|f| f.open < i && i < f.close);
+        if in_function.is_none_or(|f| f.returns_void) {
+            continue;
+        }
+        if let Some(end) = program.semicolon(i + 1).filter(|&end| end > i + 1) {
+            let expression = &program.input[program.span(i + 1, end - 1)];
+            // A null pointer constant would become an `int` temporary
+            let null =
+                matches!(expression, b"NULL" | b"nullptr") || expression.iter().all(|b| *b == b'0');
+            if end == i + 2 && null {
+                continue;
+            }
+            let replacement = [
+                b"{ __auto_type ",
+                name.as_bytes(),
+                b" = (",
+                expression,
+                b"); return ",
+                name.as_bytes(),
+                b"; }",
+            ]
+            .concat();
+            edits.push((program.span(i, end), replacement));
+        }
+    }
+    edits
+}
+
+/// Two adjacent declarations swapped, if they share no identifier and call nothing
+fn reorder(program: &Program) -> Vec<Edit> {
+    let mut statements = fragments(program.input)
+        .into_iter()
+        .filter(|f| matches!(f.kind, FragmentKind::Statement { .. }))
+        .map(|f| f.range)
+        .collect::<Vec<_>>();
+    statements.sort_by_key(|range| range.start);
+
+    // The identifiers of a declaration without side effects, `None` for anything else
+    let declaration = |range: &Range<usize>| {
+        let tokens = program.tokens_in(range);
+        if tokens.is_empty() || !SPECIFIERS.contains(&program.text(tokens.start)) {
+            return None;
+        }
+        let mut identifiers = vec![];
+        for i in tokens {
+            match program.text(i) {
+                b"(" | b"++" | b"--" => return None,
+                text if program.is_identifier(i) => identifiers.push(text),
+                _ => {}
+            }
+        }
+        Some(identifiers)
+    };
+
+    // Member order is part of the layout of a struct
+    let records = program.records();
+
+    let mut edits = vec![];
+    for pair in statements.windows(2) {
+        let (first, second) = (&pair[0], &pair[1]);
+        if first.end > second.start
+            || records
+                .iter()
+                .any(|r| r.start < first.start && first.end <= r.end)
+        {
+            continue;
+        }
+        let between = &program.input[first.end..second.start];
+        if !between.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let (Some(a), Some(b)) = (declaration(first), declaration(second)) else {
+            continue;
+        };
+        if a.iter().any(|name| b.contains(name)) {
+            continue;
+        }
+        let replacement = [
+            &program.input[second.clone()],
+            between,
+            &program.input[first.clone()],
+        ]
+        .concat();
+        edits.push((first.start..second.end, replacement));
+    }
+    edits
+}
+
+/// `for (init; cond; inc) body` as `{ init; while (cond) { body inc; } }`
+fn for_to_while(program: &Program) -> Vec<Edit> {
+    let mut edits = vec![];
+    for i in 0..program.tokens.len() {
+        if program.text(i) != b"for" || program.text(i + 1) != b"(" {
+            continue;
+        }
+        let Some(close) = program.matching(i + 1) else {
+            continue;
+        };
+        let separators = (i + 2..close)
+            .filter(|&j| program.text(j) == b";")
+            .collect::<Vec<_>>();
+        let [first, second] = separators[..] else {
+            continue;
+        };
+        // A nested statement would need its own end, keep to blocks and simple statements
+        let body_end = match program.text(close + 1) {
+            b"{" => program.matching(close + 1),
+            b"if" | b"for" | b"while" | b"do" | b"switch" | b"else" => None,
+            _ => program.semicolon(close + 1),
+        };
+        let Some(body_end) = body_end else {
+            continue;
+        };
+        // `continue` would skip the increment
+        if (close + 1..=body_end).any(|j| program.text(j) == b"continue") {
+            continue;
+        }
+
+        let part = |from: usize, to: usize| {
+            let start = program.tokens[from].end;
+            let end = program.tokens[to].start;
+            program.input[start..end].trim_ascii()
+        };
+        let (init, condition, increment) =
+            (part(i + 1, first), part(first, second), part(second, close));
+        let body = &program.input[program.span(close + 1, body_end)];
+
+        let mut replacement = b"{ ".to_vec();
+        if !init.is_empty() {
+            replacement.extend_from_slice(init);
+            replacement.extend_from_slice(b"; ");
+        }
+        replacement.extend_from_slice(b"while (");
+        replacement.extend_from_slice(if condition.is_empty() {
+            b"1"
+        } else {
+            condition
+        });
+        replacement.extend_from_slice(b") { ");
+        replacement.extend_from_slice(body);
+        if !increment.is_empty() {
+            replacement.push(b' ');
+            replacement.extend_from_slice(increment);
+            replacement.push(b';');
+        }
+        replacement.extend_from_slice(b" } }");
+        edits.push((program.span(i, body_end), replacement));
+    }
+    edits
+}
+
+/// A statement within a function in braces, unless it declares something
+fn block(program: &Program) -> Vec<Edit> {
+    let bodies = program
+        .functions()
+        .iter()
+        .map(|f| program.span(f.open, f.close))
+        .collect::<Vec<_>>();
+    let records = program.records();
+    let within = |ranges: &[Range<usize>], range: &Range<usize>| {
+        ranges
+            .iter()
+            .any(|r| r.start < range.start && range.end < r.end)
+    };
+    let statements = fragments(program.input)
+        .into_iter()
+        .filter(|f| f.kind == FragmentKind::Statement { top_level: false })
+        .filter(|f| within(&bodies, &f.range) && !within(&records, &f.range))
+        .collect::<Vec<_>>();
+    // Without braces, `if (c) x; else y;` and `do x; while (c);` are split into two
+    // statements, neither of which may be wrapped on its own
+    let mut split = vec![];
+    for statement in &statements {
+        let first = program.tokens_in(&statement.range).start;
+        let next = program
+            .tokens_in(&(statement.range.end..program.input.len()))
+            .start;
+        let unbraced_do = program.text(first) == b"do" && program.text(first + 1) != b"{";
+        if unbraced_do || program.text(next) == b"else" {
+            split.extend([first, next]);
+        }
+    }
+    statements
+        .into_iter()
+        .filter(|f| !split.contains(&program.tokens_in(&f.range).start))
+        .filter(|f| {
+            let tokens = program.tokens_in(&f.range);
+            let first = tokens.start;
+            // `T x;` with a type name `T`
+            let declares = SPECIFIERS.contains(&program.text(first))
+                || DECLARATION_STARTS.contains(&program.text(first))
+                || (tokens.len() > 1 && program.is_identifier(first) && {
+                    program.is_identifier(first + 1) || program.text(first + 1) == b"*"
+                });
+            !tokens.is_empty() && !declares
+        })
+        .map(|f| {
+            let statement = &program.input[f.range.clone()];
+            (f.range, [b"{ ", statement, b" }"].concat())
+        })
+        .collect()
+}
+
+fn nonzero(n: usize) -> NonZeroUsize {
+    NonZeroUsize::new(n).unwrap()
+}
+
+/// Applies one random rewrite of `transformation` to the valid program `input`, `None` if
+/// there is nothing to rewrite
+pub fn transform<R: Rand>(
+    rand: &mut R,
+    input: &[u8],
+    transformation: Transformation,
+) -> Option<Vec<u8>> {
+    let program = Program::new(input);
+    let edits = match transformation {
+        Transformation::Parenthesize => parenthesize(&program),
+        Transformation::Cast => cast(&program),
+        Transformation::Temporary => temporary(&program),
+        Transformation::Reorder => reorder(&program),
+        Transformation::ForToWhile => for_to_while(&program),
+        Transformation::Block => block(&program),
+    };
+    if edits.is_empty() {
+        return None;
+    }
+    let (range, replacement) = &edits[rand.below(nonzero(edits.len()))];
+    let mut transformed = input.to_vec();
+    transformed.splice(range.clone(), replacement.iter().copied());
+    Some(transformed)
+}
+
+/// Rewrites a valid program without changing its meaning, see [`Transformation`]
+#[derive(Debug, Clone)]
+pub struct ValidTransformMutator {
+    transformation: Transformation,
+    name: Cow<'static, str>,
+}
+
+impl ValidTransformMutator {
+    pub fn new(transformation: Transformation) -> Self {
+        Self {
+            transformation,
+            name: Cow::Owned(format!("ValidTransformMutator<{transformation:?}>")),
+        }
+    }
+}
+
+impl Named for ValidTransformMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<BytesInput, S> for ValidTransformMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut BytesInput) -> Result<MutationResult, Error> {
+        match transform(state.rand_mut(), input.mutator_bytes(), self.transformation) {
+            Some(transformed) => {
+                *input = BytesInput::new(transformed);
+                Ok(MutationResult::Mutated)
+            }
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _new_corpus_id: Option<libafl::corpus::CorpusId>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The tuple of [`valid_transformations`]
+pub type ValidTransformationsType = tuple_list_type!(
+    ValidTransformMutator,
+    ValidTransformMutator,
+    ValidTransformMutator,
+    ValidTransformMutator,
+    ValidTransformMutator,
+    ValidTransformMutator,
+);
+
+/// One [`ValidTransformMutator`] per [`Transformation`]
+pub fn valid_transformations() -> ValidTransformationsType {
+    tuple_list!(
+        ValidTransformMutator::new(Transformation::Parenthesize),
+        ValidTransformMutator::new(Transformation::Cast),
+        ValidTransformMutator::new(Transformation::Temporary),
+        ValidTransformMutator::new(Transformation::Reorder),
+        ValidTransformMutator::new(Transformation::ForToWhile),
+        ValidTransformMutator::new(Transformation::Block),
+    )
+}
@@ -1,9 +1,31 @@
 use std::borrow::Cow;
 
-use libafl::observers::Observer;
+use libafl::{
+    inputs::{BytesInput, EncodedInput, GramatronInput, HasTargetBytes as _, NautilusInput},
+    observers::Observer,
+};
 use libafl_bolts::Named;
 use serde::{Deserialize, Serialize};
 
+use crate::grammar::{coverage::k_paths, earley::Recognizer, RuleId};
+
+// Steps 1-23 are set by the patched clang, see `clang.diff`. The ones from 100 are reported by
+// the fuzzer itself and are listed in `LEGEND_LOOKUP` in `analyze.py`.
+
+/// Step of inputs rejected by the lexer or preprocessor
+pub const STEP_LEXING: usize = 2;
+/// Step of inputs rejected by the parser
+pub const STEP_PARSING: usize = 3;
+/// Step of inputs with errors clang did not categorize
+pub const STEP_UNKNOWN: usize = 22;
+/// Step of inputs compiled without errors
+pub const STEP_VALID: usize = 23;
+
+/// Step reported when an input could not be turned into bytes for the target
+pub const STEP_DECODING_FAILED: usize = 100;
+/// Step reported when an input decoded to nothing and the target was not run
+pub const STEP_EMPTY_INPUT: usize = 101;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CorrectnessObserver {
     #[serde(skip)]
@@ -25,6 +47,11 @@ impl CorrectnessObserver {
     pub fn step(&self) -> usize {
         self.step
     }
+
+    /// Sets the step for an execution that never reached the target, picked up in `post_exec`
+    pub fn force_step(&mut self, step: usize) {
+        unsafe { *self.step_ptr = step };
+    }
 }
 
 impl Named for CorrectnessObserver {
@@ -50,3 +77,159 @@ impl<I, S> Observer<I, S> for CorrectnessObserver {
         Ok(())
     }
 }
+
+/// Inputs that can be checked against the grammar as they are, before the target sees them
+pub trait ConformanceInput {
+    /// The bytes to check, `None` if they are only known to the executor, which then hands them
+    /// to [`GrammarConformanceObserver::observe_unparsed`]
+    fn conformance_bytes(&self) -> Option<Vec<u8>>;
+}
+
+impl ConformanceInput for BytesInput {
+    fn conformance_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.target_bytes().to_vec())
+    }
+}
+
+impl ConformanceInput for EncodedInput {
+    fn conformance_bytes(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+impl ConformanceInput for GramatronInput {
+    fn conformance_bytes(&self) -> Option<Vec<u8>> {
+        let mut bytes = vec![];
+        self.unparse(&mut bytes);
+        Some(bytes)
+    }
+}
+
+impl ConformanceInput for NautilusInput {
+    fn conformance_bytes(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Conformance {
+    /// Not checked: no grammar given, input too long or not available as bytes
+    Unknown,
+    InGrammar,
+    OutOfGrammar,
+}
+
+/// Classifies each executed input as part of the grammar's language or not.
+///
+/// Whitespace is ignored, see [`Recognizer`]. With [`Self::with_coverage`], inputs in the
+/// grammar are parsed instead, and the k-paths of their derivations are kept.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GrammarConformanceObserver {
+    #[serde(skip)]
+    recognizer: Option<Recognizer>,
+    max_len: usize,
+    conformance: Conformance,
+    coverage_k: Option<usize>,
+    #[serde(skip)]
+    rules: Vec<RuleId>,
+    #[serde(skip)]
+    paths: Vec<Vec<RuleId>>,
+    /// Whether the last input had no bytes of its own and waits for the executor's
+    #[serde(skip)]
+    unparsed_pending: bool,
+    name: Cow<'static, str>,
+}
+
+/// Name of the [`GrammarConformanceObserver`], by which executors find it
+pub const CONFORMANCE_OBSERVER: &str = "conformance";
+
+impl GrammarConformanceObserver {
+    /// Without a `recognizer`, every input is [`Conformance::Unknown`]
+    pub fn new(recognizer: Option<Recognizer>, max_len: usize) -> Self {
+        Self {
+            recognizer,
+            max_len,
+            conformance: Conformance::Unknown,
+            coverage_k: None,
+            rules: vec![],
+            paths: vec![],
+            unparsed_pending: false,
+            name: Cow::Borrowed(CONFORMANCE_OBSERVER),
+        }
+    }
+
+    /// Also keep the rules and k-paths of each input in the grammar, see
+    /// [`coverage`](crate::grammar::coverage)
+    pub fn with_coverage(mut self, k: Option<usize>) -> Self {
+        self.coverage_k = k.map(|k| k.max(1));
+        self
+    }
+
+    pub fn conformance(&self) -> Conformance {
+        self.conformance
+    }
+
+    /// The rules of the last input, empty unless it is in the grammar and coverage is enabled
+    pub fn rules(&self) -> &[RuleId] {
+        &self.rules
+    }
+
+    /// Like [`Self::rules`], for the k-paths
+    pub fn paths(&self) -> &[Vec<RuleId>] {
+        &self.paths
+    }
+
+    /// Checks the bytes an executor unparsed the last input into, if the input had none of its
+    /// own, see [`ConformanceInput::conformance_bytes`]
+    pub fn observe_unparsed(&mut self, bytes: &[u8]) {
+        if self.unparsed_pending {
+            self.unparsed_pending = false;
+            self.check(bytes);
+        }
+    }
+
+    fn check(&mut self, bytes: &[u8]) {
+        self.conformance = match &self.recognizer {
+            Some(recognizer) if bytes.len() <= self.max_len => {
+                let in_grammar = match self.coverage_k {
+                    Some(k) => recognizer.parse(bytes).map(|derivation| {
+                        self.rules = derivation.rules();
+                        self.paths = k_paths(&derivation, k);
+                    }),
+                    None => recognizer.recognizes(bytes).then_some(()),
+                };
+                if in_grammar.is_some() {
+                    Conformance::InGrammar
+                } else {
+                    Conformance::OutOfGrammar
+                }
+            }
+            _ => Conformance::Unknown,
+        };
+    }
+}
+
+impl Named for GrammarConformanceObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Observer<I, S> for GrammarConformanceObserver
+where
+    I: ConformanceInput,
+{
+    fn pre_exec(&mut self, _state: &mut S, input: &I) -> Result<(), libafl::Error> {
+        self.rules.clear();
+        self.paths.clear();
+        self.conformance = Conformance::Unknown;
+        match input.conformance_bytes() {
+            Some(bytes) => {
+                self.unparsed_pending = false;
+                self.check(&bytes);
+            }
+            None => self.unparsed_pending = true,
+        }
+        Ok(())
+    }
+}
@@ -110,17 +110,34 @@ unsafe fn write_guards() {
         let get_guard_count: fn() -> usize = get_symbol(c"get_guard_count", true);
         let guard_count = get_guard_count();
         let shmem_len = shmem_target.len();
-        if shmem_len != guard_count {
+        // With CmpLog, the comparison table follows the edges
+        let cmplog_size = if shmem_len > guard_count {
+            match std::panic::catch_unwind(|| get_symbol::<fn() -> usize>(c"get_cmplog_size", true))
+            {
+                Ok(get_cmplog_size) => get_cmplog_size(),
+                Err(e) => {
+                    log(format!("Could not get get_cmplog_size: {:?}", e));
+                    0
+                }
+            }
+        } else {
+            0
+        };
+        if shmem_len != guard_count + cmplog_size {
             log(format!(
-                "Memory sizes don't match. shmem: {}, guards: {}",
-                shmem_len, guard_count
+                "Memory sizes don't match. shmem: {}, guards: {}, cmplog: {}",
+                shmem_len, guard_count, cmplog_size
             ));
         } else {
+            let (edges, cmps) = shmem_target.split_at_mut(guard_count);
             let get_guard_values: fn() -> *const i32 = get_symbol(c"get_guard_values", true);
             let guards = get_guard_values();
             let guard_slice = slice::from_raw_parts(guards, guard_count);
-            shmem_target
-                .copy_from_slice(&guard_slice.iter().map(|&x| x as u8).collect::<Vec<u8>>());
+            edges.copy_from_slice(&guard_slice.iter().map(|&x| x as u8).collect::<Vec<u8>>());
+            if cmplog_size > 0 {
+                let get_cmplog_map: fn() -> *const u8 = get_symbol(c"get_cmplog_map", true);
+                cmps.copy_from_slice(slice::from_raw_parts(get_cmplog_map(), cmplog_size));
+            }
         }
 
         // Read the correctness step from clang's global variable directly
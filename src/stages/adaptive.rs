@@ -0,0 +1,60 @@
+use std::borrow::Cow;
+
+use libafl::{
+    events::EventFirer, executors::HasObservers, generators::Generator, state::HasExecutions, Error,
+};
+use libafl_bolts::tuples::{Handle, MatchName};
+
+use crate::{
+    feedback::report_string, observer::CorrectnessObserver,
+    stages::post_mutational::GeneratedInputHook,
+};
+
+/// A generator that learns from the correctness step its inputs reach
+pub trait AdaptiveGenerator<I, S>: Generator<I, S> {
+    /// Called with the step the last generated input reached
+    fn adapt(&mut self, state: &mut S, step: usize) -> Result<(), Error>;
+
+    /// A summary of what the generator learned so far, reported as a user stat
+    fn report(&mut self, state: &mut S) -> Result<String, Error>;
+}
+
+/// Tells an [`AdaptiveGenerator`] how far the target got with each generated input, for a
+/// [`GeneratorPostMutationalStage`](super::GeneratorPostMutationalStage).
+///
+/// What the generator learned is reported as the `rule-weights` stat.
+#[allow(unused)]
+pub struct AdaptiveGeneratorHook {
+    correctness: Handle<CorrectnessObserver>,
+}
+
+impl AdaptiveGeneratorHook {
+    #[allow(unused)]
+    pub fn new(correctness: Handle<CorrectnessObserver>) -> Self {
+        Self { correctness }
+    }
+}
+
+impl<E, EM, G, I, S> GeneratedInputHook<E, EM, G, I, S> for AdaptiveGeneratorHook
+where
+    E: HasObservers,
+    E::Observers: MatchName,
+    EM: EventFirer<I, S>,
+    G: AdaptiveGenerator<I, S>,
+    S: HasExecutions,
+{
+    fn evaluated(
+        &mut self,
+        generator: &mut G,
+        executor: &mut E,
+        state: &mut S,
+    ) -> Result<(), Error> {
+        let step = executor.observers()[&self.correctness].step();
+        generator.adapt(state, step)
+    }
+
+    fn report(&mut self, generator: &mut G, state: &mut S, manager: &mut EM) -> Result<(), Error> {
+        let report = generator.report(state)?;
+        report_string::<EM, I, S>(state, manager, Cow::Borrowed("rule-weights"), report)
+    }
+}
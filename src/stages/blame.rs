@@ -0,0 +1,273 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl::{
+    events::EventFirer,
+    executors::HasObservers,
+    generators::Generator,
+    inputs::{BytesInput, HasTargetBytes as _},
+    observers::StdErrObserver,
+    state::HasExecutions,
+    Error, HasMetadata,
+};
+use libafl_bolts::{
+    tuples::{Handle, MatchName},
+    SerdeAny,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    feedback::report_string,
+    grammar::{earley::Recognizer, Cfg, RuleId},
+    observer::{CorrectnessObserver, STEP_VALID},
+    stages::post_mutational::GeneratedInputHook,
+};
+
+/// Rules used by fewer generated inputs are left out of the `rule-blame` stat
+const MIN_REPORTED_USES: usize = 20;
+
+/// How many of the least often valid rules to report
+const REPORTED_RULES: usize = 5;
+
+/// A generator that knows which grammar rules derived its last input
+pub trait HasDerivations {
+    fn cfg(&self) -> &Cfg;
+
+    /// The distinct rules of the last generated input, empty if they are unknown
+    fn last_rules(&self) -> &[RuleId];
+}
+
+/// Recovers the derivations of a byte-level generator, e.g. a `FandangoPool`, by parsing its
+/// inputs with the grammar they were generated from.
+///
+/// Fandango's own derivation trees do not cross the Python boundary, so every input is parsed
+/// again. The Earley parse is cubic in the worst case and can take longer than running clang on
+/// large inputs, watch the `generator-time` stat.
+#[allow(unused)]
+pub struct ParsingGenerator<G> {
+    inner: G,
+    cfg: Cfg,
+    recognizer: Recognizer,
+    last_rules: Vec<RuleId>,
+}
+
+impl<G> ParsingGenerator<G> {
+    #[allow(unused)]
+    pub fn new(inner: G, cfg: Cfg) -> Self {
+        Self {
+            inner,
+            recognizer: Recognizer::new(&cfg, true),
+            cfg,
+            last_rules: vec![],
+        }
+    }
+}
+
+impl<G, S> Generator<BytesInput, S> for ParsingGenerator<G>
+where
+    G: Generator<BytesInput, S>,
+{
+    fn generate(&mut self, state: &mut S) -> Result<BytesInput, Error> {
+        let input = self.inner.generate(state)?;
+        self.last_rules = match self.recognizer.parse(&input.target_bytes()) {
+            Some(derivation) => {
+                let mut rules = derivation.rules();
+                rules.sort_unstable();
+                rules.dedup();
+                rules
+            }
+            None => vec![],
+        };
+        Ok(input)
+    }
+}
+
+impl<G> HasDerivations for ParsingGenerator<G> {
+    fn cfg(&self) -> &Cfg {
+        &self.cfg
+    }
+
+    fn last_rules(&self) -> &[RuleId] {
+        &self.last_rules
+    }
+}
+
+/// The first error clang reports, without its location
+pub fn first_diagnostic(stderr: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(stderr).lines().find_map(|line| {
+        line.find("error: ")
+            .map(|start| line[start + "error: ".len()..].trim().to_string())
+    })
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RuleStats {
+    uses: usize,
+    valid: usize,
+    diagnostics: HashMap<String, usize>,
+}
+
+/// One line of the rule blame report
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RuleBlameEntry {
+    pub rule: String,
+    /// Generated inputs whose derivation uses the rule
+    pub uses: usize,
+    /// How many of them compiled without errors
+    pub valid: usize,
+    pub fraction_valid: f64,
+    /// The most frequent first diagnostic of the invalid ones, with its count
+    pub diagnostic: Option<(String, usize)>,
+}
+
+/// Per-rule counts of how often generated inputs using a rule are valid, and which diagnostic
+/// they fail with otherwise
+#[derive(Debug, Serialize, Deserialize, SerdeAny)]
+pub struct RuleBlame {
+    rules: Vec<RuleStats>,
+    /// Generated inputs whose rules are unknown
+    unknown: usize,
+}
+
+impl RuleBlame {
+    pub fn new(cfg: &Cfg) -> Self {
+        Self {
+            rules: (0..cfg.rules.len()).map(|_| RuleStats::default()).collect(),
+            unknown: 0,
+        }
+    }
+
+    pub fn record(&mut self, rules: &[RuleId], step: usize, diagnostic: Option<String>) {
+        if rules.is_empty() {
+            self.unknown += 1;
+            return;
+        }
+        for &rule in rules {
+            let stats = &mut self.rules[rule];
+            stats.uses += 1;
+            if step == STEP_VALID {
+                stats.valid += 1;
+            } else if let Some(diagnostic) = &diagnostic {
+                *stats.diagnostics.entry(diagnostic.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// All used rules, the least often valid first
+    pub fn entries(&self, cfg: &Cfg) -> Vec<RuleBlameEntry> {
+        let mut entries = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| stats.uses > 0)
+            .map(|(rule, stats)| RuleBlameEntry {
+                rule: cfg.display_rule(rule),
+                uses: stats.uses,
+                valid: stats.valid,
+                fraction_valid: stats.valid as f64 / stats.uses as f64,
+                diagnostic: stats
+                    .diagnostics
+                    .iter()
+                    .max_by_key(|(_, &count)| count)
+                    .map(|(diagnostic, &count)| (diagnostic.clone(), count)),
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| {
+            a.fraction_valid
+                .total_cmp(&b.fraction_valid)
+                .then(b.uses.cmp(&a.uses))
+        });
+        entries
+    }
+
+    pub fn store<P: AsRef<Path>>(&self, cfg: &Cfg, path: P) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(&self.entries(cfg))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// The least often valid rules with enough uses to tell
+    pub fn summary(&self, cfg: &Cfg) -> String {
+        let worst = self
+            .entries(cfg)
+            .into_iter()
+            .filter(|entry| entry.uses >= MIN_REPORTED_USES)
+            .take(REPORTED_RULES)
+            .map(|entry| {
+                format!(
+                    "{} ({:.3} of {})",
+                    entry.rule, entry.fraction_valid, entry.uses
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        format!("unknown: {} | least valid: {worst}", self.unknown)
+    }
+}
+
+/// Records the rules of each generated input with the step and first diagnostic it gets from
+/// clang, in the [`RuleBlame`] of the state. A hook for
+/// [`GeneratorPostMutationalStage`](super::GeneratorPostMutationalStage).
+///
+/// Every report writes the blame to `file` and summarizes it in the `rule-blame` stat. Mutants are
+/// not blamed, their rules are unknown.
+#[allow(unused)]
+pub struct RuleBlameHook {
+    correctness: Handle<CorrectnessObserver>,
+    stderr: Handle<StdErrObserver>,
+    file: PathBuf,
+}
+
+impl RuleBlameHook {
+    #[allow(unused)]
+    pub fn new(
+        correctness: Handle<CorrectnessObserver>,
+        stderr: Handle<StdErrObserver>,
+        file: PathBuf,
+    ) -> Self {
+        Self {
+            correctness,
+            stderr,
+            file,
+        }
+    }
+}
+
+impl<E, EM, G, I, S> GeneratedInputHook<E, EM, G, I, S> for RuleBlameHook
+where
+    E: HasObservers,
+    E::Observers: MatchName,
+    EM: EventFirer<I, S>,
+    G: HasDerivations,
+    S: HasExecutions + HasMetadata,
+{
+    fn evaluated(
+        &mut self,
+        generator: &mut G,
+        executor: &mut E,
+        state: &mut S,
+    ) -> Result<(), Error> {
+        let observers = executor.observers();
+        let step = observers[&self.correctness].step();
+        let diagnostic = observers[&self.stderr]
+            .output
+            .as_deref()
+            .and_then(first_diagnostic);
+        state
+            .metadata_or_insert_with(|| RuleBlame::new(generator.cfg()))
+            .record(generator.last_rules(), step, diagnostic);
+        Ok(())
+    }
+
+    fn report(&mut self, generator: &mut G, state: &mut S, manager: &mut EM) -> Result<(), Error> {
+        let cfg = generator.cfg();
+        let blame = state.metadata::<RuleBlame>()?;
+        blame.store(cfg, &self.file)?;
+        let summary = blame.summary(cfg);
+        report_string::<EM, I, S>(state, manager, Cow::Borrowed("rule-blame"), summary)
+    }
+}
@@ -0,0 +1,91 @@
+use std::marker::PhantomData;
+
+use libafl::{
+    corpus::Corpus as _,
+    executors::HasObservers,
+    fuzzer::ExecutesInput,
+    observers::cmp::CmpValuesMetadata,
+    stages::{Restartable, Stage},
+    state::{HasCorpus, HasCurrentCorpusId},
+    Error, HasMetadata,
+};
+use libafl_bolts::tuples::{Handle, MatchName};
+
+use crate::cmplog::ShMemCmpObserver;
+
+/// Runs the current corpus entry again and stores the comparisons clang made for it as
+/// [`CmpValuesMetadata`], for the input-to-state mutations of a following stage.
+///
+/// The observer's table is only decoded here, so mutants of other stages cost nothing extra.
+/// Without `--cmplog` the stage does nothing, and the input-to-state mutations are skipped for
+/// lack of [`CmpValuesMetadata`].
+#[allow(unused)]
+pub struct CmpLogTracingStage<I> {
+    observer: Handle<ShMemCmpObserver>,
+    phantom: PhantomData<I>,
+}
+
+impl<I> CmpLogTracingStage<I> {
+    #[allow(unused)]
+    pub fn new(observer: Handle<ShMemCmpObserver>) -> Self {
+        Self {
+            observer,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for CmpLogTracingStage<I>
+where
+    E: HasObservers,
+    E::Observers: MatchName,
+    I: Clone,
+    S: HasCorpus<I> + HasCurrentCorpusId + HasMetadata,
+    Z: ExecutesInput<E, EM, I, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if !executor.observers()[&self.observer].is_enabled() {
+            return Ok(());
+        }
+        let Some(id) = state.current_corpus_id()? else {
+            return Ok(());
+        };
+        let input = state.corpus().cloned_input_for_id(id)?;
+        fuzzer.execute_input(state, executor, manager, &input)?;
+
+        let values = executor.observers()[&self.observer].cmp_values();
+        state.metadata_or_insert_with(CmpValuesMetadata::new).list = values;
+        Ok(())
+    }
+}
+
+impl<I, S> Restartable<S> for CmpLogTracingStage<I> {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Tracing again only costs one execution
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// With `--cmplog`, traces the comparisons of the current corpus entry and replaces their
+/// operands in it, see [`CmpLogTracingStage`]. Without it, the stages do nothing.
+#[allow(unused_macros)]
+macro_rules! setup_cmplog_stages {
+    ($cmplog:expr) => {{
+        tuple_list!(
+            crate::stages::CmpLogTracingStage::new($cmplog),
+            libafl::stages::StdMutationalStage::new(libafl::mutators::HavocScheduledMutator::new(
+                tuple_list!(libafl::mutators::I2SRandReplace::new())
+            ))
+        )
+    }};
+}
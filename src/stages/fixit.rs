@@ -0,0 +1,304 @@
+use std::{
+    borrow::Cow,
+    io::{Read as _, Write as _},
+    ops::Range,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use libafl::{
+    corpus::Corpus as _,
+    events::EventFirer,
+    executors::HasObservers,
+    fuzzer::Evaluator,
+    inputs::{BytesInput, HasMutatorBytes as _},
+    stages::{Restartable, Stage},
+    state::{HasCorpus, HasCurrentCorpusId, HasExecutions},
+    Error, HasMetadata as _,
+};
+use libafl_bolts::{
+    tuples::{Handle, MatchName},
+    SerdeAny,
+};
+use serde::{Deserialize, Serialize};
+use wait_timeout::ChildExt as _;
+
+use crate::{
+    feedback::{report_string, CorrectnessStepMetadata},
+    observer::{CorrectnessObserver, STEP_LEXING, STEP_PARSING, STEP_UNKNOWN, STEP_VALID},
+    tokenizer::LineIndex,
+};
+
+/// How many repair attempts to make between two reports of the `fixit-repair` stat
+const REPORT_INTERVAL: usize = 100;
+
+/// A replacement clang suggests for an error, as a byte range into its input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixIt {
+    pub range: Range<usize>,
+    pub replacement: Vec<u8>,
+}
+
+/// Undoes `llvm::raw_ostream::write_escaped`, which escapes `\`, `"`, tabs and newlines and
+/// writes other unprintable bytes as three octal digits
+fn unescape(escaped: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut rest = escaped;
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let (&escape, tail) = rest.split_first()?;
+        rest = tail;
+        bytes.push(match escape {
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'0'..=b'7' => {
+                let digits = [escape, *rest.first()?, *rest.get(1)?];
+                rest = &rest[2..];
+                u8::from_str_radix(std::str::from_utf8(&digits).ok()?, 8).ok()?
+            }
+            other => other,
+        });
+    }
+    Some(bytes)
+}
+
+/// The fix-its clang printed with `-fdiagnostics-parseable-fixits` for the program `input` it
+/// read from stdin, as lines like `fix-it:"<stdin>":{1:9-1:9}:";"` with 1-based columns
+pub fn parse_fixits(input: &[u8], stderr: &[u8]) -> Vec<FixIt> {
+    let lines = LineIndex::new(input);
+    let offset = |location: &[u8]| {
+        let location = std::str::from_utf8(location).ok()?;
+        let (line, column) = location.split_once(':')?;
+        lines.offset(line.parse().ok()?, column.parse().ok()?)
+    };
+
+    stderr
+        .split(|b| *b == b'\n')
+        .filter_map(|line| {
+            let line = line.strip_prefix(b"fix-it:\"<stdin>\":{")?;
+            let close = line.iter().position(|b| *b == b'}')?;
+            let (range, replacement) = (&line[..close], &line[close + 1..]);
+            let dash = range.iter().position(|b| *b == b'-')?;
+            let (start, end) = (offset(&range[..dash])?, offset(&range[dash + 1..])?);
+            let replacement = replacement.strip_prefix(b":\"")?.strip_suffix(b"\"")?;
+            (start <= end).then_some(FixIt {
+                range: start..end,
+                replacement: unescape(replacement)?,
+            })
+        })
+        .collect()
+}
+
+/// `input` with all `fixits` applied, a fix-it overlapping an earlier one is dropped.
+/// `None` if there is nothing to apply.
+pub fn apply_fixits(input: &[u8], mut fixits: Vec<FixIt>) -> Option<Vec<u8>> {
+    fixits.sort_by_key(|f| (f.range.start, f.range.end));
+    fixits.dedup();
+    let mut applied: Vec<FixIt> = vec![];
+    for fixit in fixits {
+        if applied
+            .last()
+            .is_none_or(|last| last.range.end <= fixit.range.start)
+        {
+            applied.push(fixit);
+        }
+    }
+    if applied.is_empty() {
+        return None;
+    }
+    let mut repaired = input.to_vec();
+    for fixit in applied.into_iter().rev() {
+        repaired.splice(fixit.range, fixit.replacement);
+    }
+    Some(repaired)
+}
+
+/// The position of `step` in clang's pipeline, `None` for errors clang did not categorize
+fn pipeline_rank(step: usize) -> Option<usize> {
+    match step {
+        STEP_LEXING => Some(0),
+        STEP_PARSING => Some(1),
+        STEP_VALID => Some(3),
+        STEP_UNKNOWN => None,
+        step if step > STEP_PARSING && step < STEP_UNKNOWN => Some(2),
+        _ => None,
+    }
+}
+
+/// Marks a corpus entry the [`FixItRepairStage`] already tried to repair
+#[derive(Debug, Serialize, Deserialize, SerdeAny)]
+struct FixItAttemptedMetadata;
+
+#[derive(Debug, Default)]
+struct RepairStats {
+    /// Entries clang was asked for fix-its
+    attempted: usize,
+    /// Of those, the ones clang had fix-its for
+    repaired: usize,
+    /// Repaired entries that got further in the pipeline
+    lifted: usize,
+    /// Repaired entries that compiled without errors
+    valid: usize,
+}
+
+/// For a corpus entry rejected by clang's lexer, parser or semantic analysis, asks clang for
+/// fix-its (`-fsyntax-only -fdiagnostics-parseable-fixits`), applies them and evaluates the
+/// repaired input with the fuzzer's executor.
+///
+/// Each entry is repaired once, clang is killed after `timeout` like the executions of the
+/// target. The `fixit-repair` stat counts the repaired entries that got further in clang's
+/// pipeline (lexing, parsing, semantic analysis, valid) and the valid ones.
+#[allow(unused)]
+pub struct FixItRepairStage {
+    clang: PathBuf,
+    timeout: Duration,
+    correctness: Handle<CorrectnessObserver>,
+    stats: RepairStats,
+}
+
+impl FixItRepairStage {
+    #[allow(unused)]
+    pub fn new(
+        clang: PathBuf,
+        timeout: Duration,
+        correctness: Handle<CorrectnessObserver>,
+    ) -> Self {
+        Self {
+            clang,
+            timeout,
+            correctness,
+            stats: RepairStats::default(),
+        }
+    }
+
+    /// The fix-its clang suggests for `input`, none if it times out
+    fn fixits(&self, input: &[u8]) -> Result<Vec<FixIt>, Error> {
+        let mut child = Command::new(&self.clang)
+            .args([
+                "-fsyntax-only",
+                "-fdiagnostics-parseable-fixits",
+                "-fno-color-diagnostics",
+                "-xc++",
+                "-",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // clang reads all of stdin before it reports anything
+        child.stdin.take().unwrap().write_all(input)?;
+        // Read stderr while waiting, so clang never blocks on a full pipe
+        let mut stderr = child.stderr.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut output = vec![];
+            stderr.read_to_end(&mut output).map(|_| output)
+        });
+        if child.wait_timeout(self.timeout)?.is_none() {
+            child.kill()?;
+            child.wait()?;
+            let _ = reader.join();
+            return Ok(vec![]);
+        }
+        let stderr = reader
+            .join()
+            .map_err(|_| Error::unknown("Reading clang's fix-its panicked"))??;
+        Ok(parse_fixits(input, &stderr))
+    }
+}
+
+impl<E, EM, S, Z> Stage<E, EM, S, Z> for FixItRepairStage
+where
+    E: HasObservers,
+    E::Observers: MatchName,
+    EM: EventFirer<BytesInput, S>,
+    S: HasCorpus<BytesInput> + HasCurrentCorpusId + HasExecutions,
+    Z: Evaluator<E, EM, BytesInput, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Some(id) = state.current_corpus_id()? else {
+            return Ok(());
+        };
+        let step = {
+            let mut testcase = state.corpus().get(id)?.borrow_mut();
+            if testcase.has_metadata::<FixItAttemptedMetadata>() {
+                return Ok(());
+            }
+            testcase.add_metadata(FixItAttemptedMetadata);
+            testcase
+                .metadata::<CorrectnessStepMetadata>()
+                .ok()
+                .map(|m| m.step)
+        };
+        let Some(rank) = step.and_then(pipeline_rank).filter(|rank| *rank < 3) else {
+            return Ok(());
+        };
+
+        let input = state.corpus().cloned_input_for_id(id)?;
+        let bytes = input.mutator_bytes();
+        self.stats.attempted += 1;
+        if let Some(repaired) = apply_fixits(bytes, self.fixits(bytes)?) {
+            self.stats.repaired += 1;
+            fuzzer.evaluate_input(state, executor, manager, &BytesInput::new(repaired))?;
+            let step = executor.observers()[&self.correctness].step();
+            if pipeline_rank(step).is_some_and(|new| new > rank) {
+                self.stats.lifted += 1;
+            }
+            if step == STEP_VALID {
+                self.stats.valid += 1;
+            }
+        }
+
+        if self.stats.attempted % REPORT_INTERVAL == 0 {
+            let RepairStats {
+                attempted,
+                repaired,
+                lifted,
+                valid,
+            } = self.stats;
+            let report =
+                format!("{lifted}/{repaired} lifted, {valid} valid, {attempted} attempted");
+            report_string::<EM, BytesInput, S>(
+                state,
+                manager,
+                Cow::Borrowed("fixit-repair"),
+                report,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<S> Restartable<S> for FixItRepairStage {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Entries are marked before clang runs, so a crash during the repair is not repeated
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Repairs the scheduled corpus entry with clang's fix-its, see [`FixItRepairStage`]
+#[allow(unused_macros)]
+macro_rules! setup_fixit_stages {
+    ($opt:expr, $correctness:expr) => {{
+        tuple_list!(crate::stages::FixItRepairStage::new(
+            $opt.target_binary.clone(),
+            $opt.timeout,
+            $correctness
+        ))
+    }};
+}
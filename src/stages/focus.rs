@@ -0,0 +1,106 @@
+use libafl::{
+    corpus::Corpus as _,
+    feedbacks::stdio::StdErrMetadata,
+    inputs::{BytesInput, HasMutatorBytes as _},
+    stages::{Restartable, Stage},
+    state::{HasCorpus, HasCurrentCorpusId},
+    Error, HasMetadata,
+};
+
+use crate::{
+    mutators::focus::{focus_window, DiagnosticFocusMetadata},
+    tokenizer::offset_of,
+};
+
+/// The line and column of the first error clang reports, if it is in the input read from stdin
+pub fn first_error_location(stderr: &str) -> Option<(usize, usize)> {
+    let line = stderr.lines().find(|line| line.contains("error: "))?;
+    let (location, _) = line.strip_prefix("<stdin>:")?.split_once(": ")?;
+    let (line, column) = location.split_once(':')?;
+    Some((line.parse().ok()?, column.parse().ok()?))
+}
+
+/// Runs the inner stage, usually one with
+/// [`diagnostic_focus_mutations`](crate::mutators::diagnostic_focus_mutations), with the
+/// window around the first error of the current corpus entry as [`DiagnosticFocusMetadata`].
+///
+/// The error is taken from the stderr that `StdErrToMetadataFeedback` stored with the entry,
+/// entries without an error in their input are skipped.
+#[allow(unused)]
+pub struct DiagnosticFocusStage<ST> {
+    inner: ST,
+}
+
+impl<ST> DiagnosticFocusStage<ST> {
+    #[allow(unused)]
+    pub fn new(inner: ST) -> Self {
+        Self { inner }
+    }
+}
+
+impl<E, EM, S, ST, Z> Stage<E, EM, S, Z> for DiagnosticFocusStage<ST>
+where
+    S: HasCorpus<BytesInput> + HasCurrentCorpusId + HasMetadata,
+    ST: Stage<E, EM, S, Z>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Some(id) = state.current_corpus_id()? else {
+            return Ok(());
+        };
+        let location = state
+            .corpus()
+            .get(id)?
+            .borrow()
+            .metadata::<StdErrMetadata>()
+            .ok()
+            .and_then(|m| first_error_location(&m.stderr));
+        let Some((line, column)) = location else {
+            return Ok(());
+        };
+        let input = state.corpus().cloned_input_for_id(id)?;
+        let Some(offset) = offset_of(input.mutator_bytes(), line, column) else {
+            return Ok(());
+        };
+
+        let window = focus_window(input.mutator_bytes(), offset);
+        state.add_metadata(DiagnosticFocusMetadata { window });
+        let result = self.inner.perform(fuzzer, executor, state, manager);
+        // The window belongs to this entry, mutators of other stages must not see it
+        state.metadata_map_mut().remove::<DiagnosticFocusMetadata>();
+        result
+    }
+}
+
+impl<S, ST> Restartable<S> for DiagnosticFocusStage<ST>
+where
+    ST: Restartable<S>,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        self.inner.should_restart(state)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.clear_progress(state)
+    }
+}
+
+/// Mutates the scheduled corpus entry near its first error, see [`DiagnosticFocusStage`]
+#[allow(unused_macros)]
+macro_rules! setup_diagnostic_focus_stages {
+    () => {{
+        tuple_list!(crate::stages::DiagnosticFocusStage::new(
+            libafl::stages::StdMutationalStage::new(
+                libafl::mutators::HavocScheduledMutator::with_max_stack_pow(
+                    crate::mutators::diagnostic_focus_mutations(),
+                    2
+                )
+            )
+        ))
+    }};
+}
@@ -0,0 +1,19 @@
+pub mod adaptive;
+pub mod blame;
+#[macro_use]
+pub mod cmplog;
+#[macro_use]
+pub mod fixit;
+#[macro_use]
+pub mod focus;
+pub mod nautilus_chunks;
+pub mod post_mutational;
+#[macro_use]
+pub mod valid;
+
+#[allow(unused_imports)]
+pub use {
+    adaptive::AdaptiveGeneratorHook, blame::RuleBlameHook, cmplog::CmpLogTracingStage,
+    fixit::FixItRepairStage, focus::DiagnosticFocusStage, nautilus_chunks::NautilusChunksStage,
+    post_mutational::GeneratorPostMutationalStage, valid::ValidEntriesStage,
+};
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use libafl::{
+    corpus::Corpus as _,
+    feedbacks::NautilusChunksMetadata,
+    generators::NautilusContext,
+    inputs::NautilusInput,
+    stages::{Restartable, Stage},
+    state::HasCorpus,
+    Error, HasMetadata,
+};
+use libafl_bolts::SerdeAny;
+use serde::{Deserialize, Serialize};
+
+/// How many corpus entries the [`NautilusChunksStage`] added to the [`NautilusChunksMetadata`]
+#[derive(Debug, Default, Serialize, Deserialize, SerdeAny)]
+struct NautilusChunksAddedMetadata {
+    added: usize,
+}
+
+/// Adds the trees of new corpus entries to the [`NautilusChunksMetadata`] that
+/// `NautilusSpliceMutator` takes subtrees from.
+///
+/// In a fuzzer built around Nautilus, `NautilusFeedback` does this. Here the feedbacks are shared
+/// by all configs, so the stage catches up with the corpus before each mutational stage instead.
+#[allow(unused)]
+pub struct NautilusChunksStage<'a> {
+    context: &'a NautilusContext,
+    work_dir: PathBuf,
+}
+
+impl<'a> NautilusChunksStage<'a> {
+    #[allow(unused)]
+    pub fn new(context: &'a NautilusContext, work_dir: PathBuf) -> Self {
+        Self { context, work_dir }
+    }
+}
+
+impl<E, EM, S, Z> Stage<E, EM, S, Z> for NautilusChunksStage<'_>
+where
+    S: HasCorpus<NautilusInput> + HasMetadata,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        if !state.has_metadata::<NautilusChunksMetadata>() {
+            std::fs::create_dir_all(&self.work_dir)?;
+            state.add_metadata(NautilusChunksMetadata::new(
+                self.work_dir.to_string_lossy().to_string(),
+            ));
+        }
+        let mut added = state
+            .metadata_or_insert_with(NautilusChunksAddedMetadata::default)
+            .added;
+        while added < state.corpus().count() {
+            let id = state.corpus().nth(added);
+            let input = state.corpus().cloned_input_for_id(id)?;
+            state
+                .metadata_mut::<NautilusChunksMetadata>()?
+                .cks
+                .add_tree(input.tree().clone(), &self.context.ctx);
+            added += 1;
+            state.metadata_mut::<NautilusChunksAddedMetadata>()?.added = added;
+        }
+        Ok(())
+    }
+}
+
+impl<S> Restartable<S> for NautilusChunksStage<'_> {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
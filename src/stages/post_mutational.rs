@@ -0,0 +1,211 @@
+use std::{borrow::Cow, marker::PhantomData, time::Duration};
+
+use libafl::{
+    events::EventFirer,
+    fuzzer::Evaluator,
+    generators::Generator,
+    monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+    mutators::{MutationResult, Mutator},
+    stages::{Restartable, Stage},
+    state::{HasExecutions, HasRand},
+    Error,
+};
+use libafl_bolts::{current_time, rands::Rand as _};
+
+use crate::feedback::report_stat;
+
+/// How many generated inputs to process between two timing reports
+const REPORT_INTERVAL: usize = 100;
+
+/// Feedback on each input a [`GeneratorPostMutationalStage`] generates, given after the input
+/// was evaluated and before its mutants are
+pub trait GeneratedInputHook<E, EM, G, I, S> {
+    /// Called with the executor that just ran the last input of `generator`
+    fn evaluated(
+        &mut self,
+        generator: &mut G,
+        executor: &mut E,
+        state: &mut S,
+    ) -> Result<(), Error>;
+
+    /// Reports what the hook learned so far, along with the timing stats of the stage
+    fn report(&mut self, generator: &mut G, state: &mut S, manager: &mut EM) -> Result<(), Error>;
+}
+
+impl<E, EM, G, I, S> GeneratedInputHook<E, EM, G, I, S> for () {
+    fn evaluated(
+        &mut self,
+        _generator: &mut G,
+        _executor: &mut E,
+        _state: &mut S,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn report(
+        &mut self,
+        _generator: &mut G,
+        _state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Generates a fresh input, evaluates it and then evaluates between `min` and `max` mutants of it.
+///
+/// Each mutant starts from the unaltered generated input, so this is the generator-agnostic
+/// equivalent of Fandango's `FandangoPostMutationalStage`. The time spent waiting for the
+/// generator and the time spent evaluating inputs are reported separately as user stats.
+/// A [`GeneratedInputHook`] set with [`Self::with_hook`] sees each generated input's run.
+#[allow(unused)]
+pub struct GeneratorPostMutationalStage<G, H, I, M> {
+    generator: G,
+    hook: H,
+    mutator: M,
+    min: usize,
+    max: usize,
+    generator_time: Duration,
+    target_time: Duration,
+    generated: usize,
+    phantom: PhantomData<I>,
+}
+
+impl<G, I, M> GeneratorPostMutationalStage<G, (), I, M> {
+    #[allow(unused)]
+    pub fn new(generator: G, mutator: M, min: usize, max: usize) -> Self {
+        assert!(min <= max, "min post mutations must not exceed max");
+        Self {
+            generator,
+            hook: (),
+            mutator,
+            min,
+            max,
+            generator_time: Duration::ZERO,
+            target_time: Duration::ZERO,
+            generated: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Calls `hook` for each generated input
+    #[allow(unused)]
+    pub fn with_hook<H>(self, hook: H) -> GeneratorPostMutationalStage<G, H, I, M> {
+        GeneratorPostMutationalStage {
+            generator: self.generator,
+            hook,
+            mutator: self.mutator,
+            min: self.min,
+            max: self.max,
+            generator_time: self.generator_time,
+            target_time: self.target_time,
+            generated: self.generated,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Evaluates between `min` and `max` mutants of `input`, each mutated from the original
+#[allow(clippy::too_many_arguments)]
+fn evaluate_mutants<E, EM, I, M, S, Z>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut S,
+    manager: &mut EM,
+    mutator: &mut M,
+    input: &I,
+    min: usize,
+    max: usize,
+) -> Result<(), Error>
+where
+    I: Clone,
+    M: Mutator<I, S>,
+    S: HasRand,
+    Z: Evaluator<E, EM, I, S>,
+{
+    let mutations = state.rand_mut().between(min, max);
+    for _ in 0..mutations {
+        let mut mutant = input.clone();
+        if mutator.mutate(state, &mut mutant)? == MutationResult::Skipped {
+            continue;
+        }
+        let (_, corpus_id) = fuzzer.evaluate_filtered(state, executor, manager, &mutant)?;
+        mutator.post_exec(state, corpus_id)?;
+    }
+    Ok(())
+}
+
+fn report_time<EM, I, S>(
+    state: &mut S,
+    manager: &mut EM,
+    name: &'static str,
+    time: Duration,
+) -> Result<(), Error>
+where
+    EM: EventFirer<I, S>,
+    S: HasExecutions,
+{
+    let value = UserStats::new(
+        UserStatsValue::Float(time.as_secs_f64()),
+        AggregatorOps::Sum,
+    );
+    report_stat::<EM, I, S>(state, manager, Cow::Borrowed(name), value)
+}
+
+impl<E, EM, G, H, I, M, S, Z> Stage<E, EM, S, Z> for GeneratorPostMutationalStage<G, H, I, M>
+where
+    EM: EventFirer<I, S>,
+    G: Generator<I, S>,
+    H: GeneratedInputHook<E, EM, G, I, S>,
+    M: Mutator<I, S>,
+    I: Clone,
+    S: HasRand + HasExecutions,
+    Z: Evaluator<E, EM, I, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let start = current_time();
+        let generated = self.generator.generate(state)?;
+        let generated_at = current_time();
+        self.generator_time += generated_at - start;
+
+        fuzzer.evaluate_input(state, executor, manager, &generated)?;
+        self.hook.evaluated(&mut self.generator, executor, state)?;
+
+        evaluate_mutants(
+            fuzzer,
+            executor,
+            state,
+            manager,
+            &mut self.mutator,
+            &generated,
+            self.min,
+            self.max,
+        )?;
+        self.target_time += current_time() - generated_at;
+
+        self.generated += 1;
+        if self.generated % REPORT_INTERVAL == 0 {
+            report_time::<EM, I, S>(state, manager, "generator-time", self.generator_time)?;
+            report_time::<EM, I, S>(state, manager, "target-time", self.target_time)?;
+            self.hook.report(&mut self.generator, state, manager)?;
+        }
+        Ok(())
+    }
+}
+
+impl<G, H, I, M, S> Restartable<S> for GeneratorPostMutationalStage<G, H, I, M> {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Each run generates a new input, so there is no progress to lose
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::marker::PhantomData;
+
+use libafl::{
+    corpus::Corpus as _,
+    stages::{Restartable, Stage},
+    state::{HasCorpus, HasCurrentCorpusId},
+    Error, HasMetadata as _,
+};
+
+use crate::{feedback::CorrectnessStepMetadata, observer::STEP_VALID};
+
+/// Runs the inner stage only for corpus entries that clang accepted, as recorded by
+/// [`ReportCorrectnessFeedback`](crate::feedback::ReportCorrectnessFeedback)
+#[allow(unused)]
+pub struct ValidEntriesStage<I, ST> {
+    inner: ST,
+    phantom: PhantomData<I>,
+}
+
+impl<I, ST> ValidEntriesStage<I, ST> {
+    #[allow(unused)]
+    pub fn new(inner: ST) -> Self {
+        Self {
+            inner,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, I, S, ST, Z> Stage<E, EM, S, Z> for ValidEntriesStage<I, ST>
+where
+    S: HasCorpus<I> + HasCurrentCorpusId,
+    ST: Stage<E, EM, S, Z>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Some(id) = state.current_corpus_id()? else {
+            return Ok(());
+        };
+        let valid = state
+            .corpus()
+            .get(id)?
+            .borrow()
+            .metadata::<CorrectnessStepMetadata>()
+            .is_ok_and(|m| m.step == STEP_VALID);
+        if valid {
+            self.inner.perform(fuzzer, executor, state, manager)?;
+        }
+        Ok(())
+    }
+}
+
+impl<I, S, ST> Restartable<S> for ValidEntriesStage<I, ST>
+where
+    ST: Restartable<S>,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        self.inner.should_restart(state)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.clear_progress(state)
+    }
+}
+
+/// Semantics-preserving rewrites of the valid corpus entries, see
+/// [`valid_transformations`](crate::mutators::valid_transformations)
+#[allow(unused_macros)]
+macro_rules! setup_valid_transform_stages {
+    () => {{
+        tuple_list!(crate::stages::ValidEntriesStage::new(
+            libafl::stages::StdMutationalStage::new(
+                libafl::mutators::HavocScheduledMutator::with_max_stack_pow(
+                    crate::mutators::valid_transformations(),
+                    3
+                )
+            )
+        ))
+    }};
+}
@@ -0,0 +1,397 @@
+use libafl::{inputs::Tokenizer, Error};
+
+/// The lexical class of a [`Token`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Identifier,
+    Number,
+    CharLiteral,
+    StringLiteral,
+    Punctuator,
+    /// A `//` or `/* */` comment, line comments include their terminating newline
+    Comment,
+    /// A preprocessor directive up to and including its (possibly continued) newline
+    Directive,
+    /// Any byte that does not start a valid C token
+    Other,
+}
+
+/// A token as a byte range into the lexed input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Multi-character punctuators, longest first so the first match is the maximal munch
+const PUNCTUATORS: &[&[u8]] = &[
+    b"%:%:", b"...", b"<<=", b">>=", b"->*", b"<=>", b"->", b"++", b"--", b"<<", b">>", b"<=",
+    b">=", b"==", b"!=", b"&&", b"||", b"*=", b"/=", b"%=", b"+=", b"-=", b"&=", b"^=", b"|=",
+    b"##", b"::", b".*", b"<:", b":>", b"<%", b"%>", b"%:",
+];
+
+const SINGLE_PUNCTUATORS: &[u8] = b"[](){}.&*+-~!/%<>^|?:;=,#";
+
+fn is_ident_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_' || b == b'$' || b >= 0x80
+}
+
+fn is_ident_continue(b: u8) -> bool {
+    is_ident_start(b) || b.is_ascii_digit()
+}
+
+/// Splits C/C++ source into tokens, never failing on malformed input.
+///
+/// Whitespace is dropped, everything else is covered by exactly one token.
+#[must_use]
+pub fn lex(input: &[u8]) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+    let mut line_start = true;
+
+    while pos < input.len() {
+        let b = input[pos];
+        if b.is_ascii_whitespace() {
+            if b == b'\n' {
+                line_start = true;
+            }
+            pos += 1;
+            continue;
+        }
+
+        let start = pos;
+        let kind = if b == b'#' && line_start {
+            pos = directive_end(input, pos);
+            TokenKind::Directive
+        } else if input[pos..].starts_with(b"//") {
+            pos = line_end(input, pos);
+            TokenKind::Comment
+        } else if input[pos..].starts_with(b"/*") {
+            pos = input[pos + 2..]
+                .windows(2)
+                .position(|w| w == b"*/")
+                .map_or(input.len(), |p| pos + 2 + p + 2);
+            TokenKind::Comment
+        } else if let Some((end, kind)) = prefixed_literal(input, pos) {
+            pos = end;
+            kind
+        } else if is_ident_start(b) {
+            while pos < input.len() && is_ident_continue(input[pos]) {
+                pos += 1;
+            }
+            TokenKind::Identifier
+        } else if b.is_ascii_digit()
+            || (b == b'.' && input.get(pos + 1).is_some_and(u8::is_ascii_digit))
+        {
+            pos = number_end(input, pos);
+            TokenKind::Number
+        } else if b == b'"' {
+            pos = quoted_end(input, pos, b'"');
+            TokenKind::StringLiteral
+        } else if b == b'\'' {
+            pos = quoted_end(input, pos, b'\'');
+            TokenKind::CharLiteral
+        } else if let Some(p) = PUNCTUATORS.iter().find(|p| input[pos..].starts_with(p)) {
+            pos += p.len();
+            TokenKind::Punctuator
+        } else if SINGLE_PUNCTUATORS.contains(&b) {
+            pos += 1;
+            TokenKind::Punctuator
+        } else {
+            pos += 1;
+            TokenKind::Other
+        };
+
+        line_start = matches!(kind, TokenKind::Directive)
+            || (matches!(kind, TokenKind::Comment) && input[pos - 1] == b'\n');
+        tokens.push(Token {
+            kind,
+            start,
+            end: pos,
+        });
+    }
+    tokens
+}
+
+/// End of a line, including the newline itself
+fn line_end(input: &[u8], pos: usize) -> usize {
+    input[pos..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(input.len(), |p| pos + p + 1)
+}
+
+/// End of a preprocessor directive, following backslash-newline continuations
+fn directive_end(input: &[u8], mut pos: usize) -> usize {
+    loop {
+        let end = line_end(input, pos);
+        let line = &input[pos..end];
+        let continued = line.strip_suffix(b"\n").is_some_and(|l| {
+            l.strip_suffix(b"\r")
+                .unwrap_or(l)
+                .last()
+                .is_some_and(|&b| b == b'\\')
+        });
+        if !continued || end == input.len() {
+            return end;
+        }
+        pos = end;
+    }
+}
+
+/// End of a preprocessing number, i.e. digits, suffixes, exponents and digit separators
+fn number_end(input: &[u8], mut pos: usize) -> usize {
+    pos += 1;
+    while pos < input.len() {
+        let b = input[pos];
+        let prev = input[pos - 1];
+        if (b == b'+' || b == b'-') && matches!(prev, b'e' | b'E' | b'p' | b'P') {
+            pos += 1;
+        } else if b == b'\'' && input.get(pos + 1).is_some_and(u8::is_ascii_alphanumeric) {
+            pos += 2;
+        } else if b.is_ascii_alphanumeric() || b == b'_' || b == b'.' {
+            pos += 1;
+        } else {
+            break;
+        }
+    }
+    pos
+}
+
+/// End of a quoted literal starting at `pos`, unterminated literals end at the newline
+fn quoted_end(input: &[u8], mut pos: usize, quote: u8) -> usize {
+    pos += 1;
+    while pos < input.len() {
+        match input[pos] {
+            b'\\' => pos += 2,
+            b'\n' => return pos,
+            b if b == quote => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    input.len()
+}
+
+/// Handles encoding-prefixed (`L`, `u`, `U`, `u8`) and raw (`R"delim(...)delim"`) literals
+fn prefixed_literal(input: &[u8], pos: usize) -> Option<(usize, TokenKind)> {
+    let rest = &input[pos..];
+    let prefix_len = [&b"u8"[..], b"u", b"U", b"L", b""]
+        .iter()
+        .find(|p| rest.starts_with(p) && rest.len() > p.len())
+        .map(|p| p.len())?;
+    let after_prefix = &rest[prefix_len..];
+
+    if after_prefix.starts_with(b"R\"") {
+        let delim_start = pos + prefix_len + 2;
+        let open = input[delim_start..]
+            .iter()
+            .take(17)
+            .position(|&b| b == b'(')?;
+        let mut close = Vec::with_capacity(open + 2);
+        close.push(b')');
+        close.extend_from_slice(&input[delim_start..delim_start + open]);
+        close.push(b'"');
+        let body = delim_start + open + 1;
+        let end = input[body..]
+            .windows(close.len())
+            .position(|w| w == close.as_slice())
+            .map_or(input.len(), |p| body + p + close.len());
+        return Some((end, TokenKind::StringLiteral));
+    }
+
+    if prefix_len == 0 {
+        return None;
+    }
+    match after_prefix[0] {
+        b'"' => Some((
+            quoted_end(input, pos + prefix_len, b'"'),
+            TokenKind::StringLiteral,
+        )),
+        b'\'' => Some((
+            quoted_end(input, pos + prefix_len, b'\''),
+            TokenKind::CharLiteral,
+        )),
+        _ => None,
+    }
+}
+
+/// The tokens of `source` without directives and comments, separated by single spaces
+pub fn strip_preprocessor(source: &[u8]) -> Vec<u8> {
+    let mut stripped = vec![];
+    for token in lex(source) {
+        if matches!(token.kind, TokenKind::Directive | TokenKind::Comment) {
+            continue;
+        }
+        if !stripped.is_empty() {
+            stripped.push(b' ');
+        }
+        stripped.extend_from_slice(&source[token.start..token.end]);
+    }
+    stripped
+}
+
+/// The starts of the lines of an input, to map many locations clang reports to byte offsets
+pub struct LineIndex {
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(input: &[u8]) -> Self {
+        let starts = std::iter::once(0)
+            .chain(
+                input
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| **b == b'\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        Self {
+            starts,
+            len: input.len(),
+        }
+    }
+
+    /// The byte offset of a 1-based line and byte column
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let offset = self.starts.get(line.checked_sub(1)?)? + column.checked_sub(1)?;
+        (offset <= self.len).then_some(offset)
+    }
+}
+
+/// The byte offset of a location as clang reports it, with a 1-based line and byte column
+pub fn offset_of(input: &[u8], line: usize, column: usize) -> Option<usize> {
+    LineIndex::new(input).offset(line, column)
+}
+
+/// A [`Tokenizer`] that splits inputs into real C/C++ tokens for the token-level encoders.
+///
+/// Comments and directives are kept as single tokens (including their trailing newline), so
+/// re-joining the tokens with spaces yields an equivalent program.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CTokenizer;
+
+impl Tokenizer for CTokenizer {
+    fn tokenize(&self, bytes: &[u8]) -> Result<Vec<String>, Error> {
+        let source = core::str::from_utf8(bytes)
+            .map_err(|e| Error::illegal_argument(format!("Input is not valid UTF-8: {e}")))?;
+        // All token boundaries are at ASCII bytes, so slicing never splits a character
+        Ok(lex(bytes)
+            .iter()
+            .map(|t| source[t.start..t.end].to_string())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::inputs::Tokenizer as _;
+
+    use super::{lex, offset_of, strip_preprocessor, CTokenizer, TokenKind};
+
+    fn tokens(input: &str) -> Vec<(TokenKind, &str)> {
+        lex(input.as_bytes())
+            .into_iter()
+            .map(|t| (t.kind, &input[t.start..t.end]))
+            .collect()
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            tokens("a // b\n/* c\n d */ e"),
+            [
+                (TokenKind::Identifier, "a"),
+                (TokenKind::Comment, "// b\n"),
+                (TokenKind::Comment, "/* c\n d */"),
+                (TokenKind::Identifier, "e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn directives() {
+        // Only a `#` at the start of a line begins a directive, which may be continued
+        assert_eq!(
+            tokens("#define A \\\n 1\nA # B\n  #if A\n"),
+            [
+                (TokenKind::Directive, "#define A \\\n 1\n"),
+                (TokenKind::Identifier, "A"),
+                (TokenKind::Punctuator, "#"),
+                (TokenKind::Identifier, "B"),
+                (TokenKind::Directive, "#if A\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_strings() {
+        assert_eq!(
+            tokens(r#"R"x()")x" u8R"(a)" R"#),
+            [
+                (TokenKind::StringLiteral, r#"R"x()")x""#),
+                (TokenKind::StringLiteral, r#"u8R"(a)""#),
+                (TokenKind::Identifier, "R"),
+            ]
+        );
+    }
+
+    #[test]
+    fn character_literals() {
+        assert_eq!(
+            tokens(r"'a' '\'' L'b' u8'c' 'd"),
+            [
+                (TokenKind::CharLiteral, "'a'"),
+                (TokenKind::CharLiteral, r"'\''"),
+                (TokenKind::CharLiteral, "L'b'"),
+                (TokenKind::CharLiteral, "u8'c'"),
+                (TokenKind::CharLiteral, "'d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn pp_numbers() {
+        assert_eq!(
+            tokens("1e+5 0x1p-3f .5 1'000'000ull 1.e-x+1"),
+            [
+                (TokenKind::Number, "1e+5"),
+                (TokenKind::Number, "0x1p-3f"),
+                (TokenKind::Number, ".5"),
+                (TokenKind::Number, "1'000'000ull"),
+                (TokenKind::Number, "1.e-x"),
+                (TokenKind::Punctuator, "+"),
+                (TokenKind::Number, "1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn stripped_preprocessor() {
+        assert_eq!(
+            strip_preprocessor(b"#include <a.h>\nint  x; // y\n"),
+            b"int x ;"
+        );
+    }
+
+    #[test]
+    fn c_tokenizer() {
+        assert_eq!(
+            CTokenizer.tokenize("a+=\"b c\";".as_bytes()).unwrap(),
+            ["a", "+=", "\"b c\"", ";"]
+        );
+        assert!(CTokenizer.tokenize(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn offsets() {
+        let input = b"ab\ncd\n";
+        assert_eq!(offset_of(input, 1, 1), Some(0));
+        assert_eq!(offset_of(input, 2, 2), Some(4));
+        assert_eq!(offset_of(input, 3, 1), Some(6));
+        assert_eq!(offset_of(input, 3, 2), None);
+        assert_eq!(offset_of(input, 0, 1), None);
+    }
+}